@@ -0,0 +1,245 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use twilight_model::{
+	application::{
+		command::{Command, CommandType},
+		interaction::{application_command::{CommandDataOption, CommandOptionValue}, ApplicationCommand},
+	},
+	channel::ChannelType,
+	guild::Permissions,
+	id::{marker::{ChannelMarker, RoleMarker, UserMarker}, Id},
+};
+use twilight_util::builder::command::{
+	ChannelBuilder, CommandBuilder, RoleBuilder, StringBuilder, SubCommandBuilder, SubCommandGroupBuilder, UserBuilder,
+};
+
+use crate::audit;
+use crate::error::BotError;
+use crate::handler::{Context, Invoker, SlashCommand};
+use crate::settings;
+use crate::storage::{self, Setting, UserBan};
+
+/// `/admin`, changes the run channels and who's banned without touching the
+/// config. Only for members with Manage Server.
+pub struct AdminCommand;
+
+#[async_trait]
+impl SlashCommand for AdminCommand {
+	fn command(&self) -> Command {
+		let channel = || ChannelBuilder::new("channel".into(), "The channel".into())
+			.channel_types([ChannelType::GuildText])
+			.required(true);
+		let role = || RoleBuilder::new("role".into(), "The role".into()).required(true);
+
+		CommandBuilder::new(
+			"admin".into(),
+			"Change where code gets run and who can use the bot.".into(),
+			CommandType::ChatInput,
+		)
+		.option(SubCommandGroupBuilder::new("channel".into(), "The channels code in messages gets run in".into())
+			.subcommands([
+				SubCommandBuilder::new("add".into(), "Run code in messages in a channel".into()).option(channel()),
+				SubCommandBuilder::new("remove".into(), "Stop running code in messages in a channel".into()).option(channel()),
+				SubCommandBuilder::new("list".into(), "List the channels code gets run in".into()),
+			]))
+		.option(SubCommandGroupBuilder::new("ban-role".into(), "Roles that can't use the bot".into())
+			.subcommands([
+				SubCommandBuilder::new("add".into(), "Stop a role from using the bot".into()).option(role()),
+				SubCommandBuilder::new("remove".into(), "Let a role use the bot again".into()).option(role()),
+			]))
+		.option(SubCommandBuilder::new("ban-user".into(), "Stop someone from using the bot".into())
+			.option(UserBuilder::new("user".into(), "Who to ban".into()).required(true))
+			.option(StringBuilder::new("duration".into(), "How long for, like 30m, 12h or 7d. For good without it".into()))
+			.option(StringBuilder::new("reason".into(), "Why".into())))
+		.option(SubCommandBuilder::new("unban-user".into(), "Let someone use the bot again".into())
+			.option(UserBuilder::new("user".into(), "Who to unban".into()).required(true)))
+		.build()
+	}
+
+	async fn run(&self, ctx: &Context, cmd: &ApplicationCommand, invoker: &Invoker) -> Result<(), BotError> {
+		let permissions = ctx.cache.permissions()
+			.root(invoker.user.id, invoker.guild)
+			.map_err(|_| BotError::Validation(String::from("Couldn't work out your permissions, try again in a bit.")))?;
+		if !permissions.contains(Permissions::MANAGE_GUILD) {
+			return Err(BotError::Permission(String::from("You need Manage Server to use that.")));
+		}
+
+		let (path, options) = subcommand(&cmd.data.options)
+			.ok_or_else(|| BotError::Validation(String::from("That isn't an admin command.")))?;
+		let guild = invoker.guild;
+
+		let content = match path.as_slice() {
+			["channel", action @ ("add" | "remove")] => {
+				let channel: Id<ChannelMarker> = id(options, "channel")?;
+				settings::set(guild, Setting::RunChannel, channel.get(), *action == "add")?;
+				if *action == "add" {
+					format!("👍 Code in <#{}> gets run now.", channel)
+				} else {
+					format!("👍 Code in <#{}> doesn't get run anymore.", channel)
+				}
+			}
+			["channel", "list"] => {
+				let channels = settings::get(guild, Setting::RunChannel);
+				if channels.is_empty() {
+					String::from("Code doesn't get run in any channels.")
+				} else {
+					let channels = channels.iter().map(|channel| format!("<#{}>", channel)).collect::<Vec<_>>();
+					format!("Code gets run in {}.", channels.join(", "))
+				}
+			}
+			["ban-role", action @ ("add" | "remove")] => {
+				let role: Id<RoleMarker> = id(options, "role")?;
+				settings::set(guild, Setting::BannedRole, role.get(), *action == "add")?;
+				if *action == "add" {
+					format!("👍 <@&{}> can't use the bot anymore.", role)
+				} else {
+					format!("👍 <@&{}> can use the bot again.", role)
+				}
+			}
+			["ban-user"] => {
+				let user: Id<UserMarker> = id(options, "user")?;
+				let until = match string(options, "duration") {
+					Some(duration) => Some(parse_duration(duration)
+						.and_then(|duration| SystemTime::now().checked_add(duration))
+						.and_then(|until| until.duration_since(UNIX_EPOCH).ok())
+						.and_then(|until| i64::try_from(until.as_secs()).ok())
+						.ok_or_else(|| BotError::Validation(format!(
+							"`{}` isn't a duration, try something like 30m, 12h or 7d.", duration
+						)))?),
+					None => None,
+				};
+
+				storage::get().ban_user(&UserBan {
+					user,
+					guild,
+					moderator: invoker.user.id,
+					reason: string(options, "reason").map(String::from),
+					until,
+				})?;
+				match until {
+					Some(until) => format!("🔨 <@{}> can't use the bot until <t:{}:f>.", user, until),
+					None => format!("🔨 <@{}> can't use the bot anymore.", user),
+				}
+			}
+			["unban-user"] => {
+				let user: Id<UserMarker> = id(options, "user")?;
+				if storage::get().unban_user(guild, user)? {
+					format!("👍 <@{}> can use the bot again.", user)
+				} else {
+					format!("<@{}> wasn't banned.", user)
+				}
+			}
+			_ => return Err(BotError::Validation(String::from("That isn't an admin command."))),
+		};
+
+		ctx.respond_ephemeral(cmd.id, &cmd.token, &content).await?;
+
+		if path != ["channel", "list"] {
+			audit::log(&ctx.http, guild, audit::Entry::Admin {
+				moderator: invoker.user.id,
+				change: content,
+				reason: string(options, "reason").map(String::from),
+			}).await;
+		}
+		Ok(())
+	}
+}
+
+/// Which subcommand was used (like `["channel", "add"]`), and its options.
+fn subcommand(options: &[CommandDataOption]) -> Option<(Vec<&str>, &[CommandDataOption])> {
+	let option = options.first()?;
+	match &option.value {
+		CommandOptionValue::SubCommandGroup(options) => {
+			let (mut path, options) = subcommand(options)?;
+			path.insert(0, option.name.as_str());
+			Some((path, options))
+		}
+		CommandOptionValue::SubCommand(options) => Some((vec![option.name.as_str()], options)),
+		_ => None,
+	}
+}
+
+/// A required channel, role or user option.
+fn id<T>(options: &[CommandDataOption], name: &str) -> Result<Id<T>, BotError> {
+	options.iter()
+		.find(|option| option.name == name)
+		.and_then(|option| match option.value {
+			CommandOptionValue::Channel(id) => Some(id.cast()),
+			CommandOptionValue::Role(id) => Some(id.cast()),
+			CommandOptionValue::User(id) => Some(id.cast()),
+			_ => None,
+		})
+		.ok_or_else(|| BotError::Validation(format!("The {} you picked was mangled by Discord?", name)))
+}
+
+fn string<'a>(options: &'a [CommandDataOption], name: &str) -> Option<&'a str> {
+	options.iter().find_map(|option| match &option.value {
+		CommandOptionValue::String(value) if option.name == name => Some(value.as_str()),
+		_ => None,
+	})
+}
+
+/// Durations like `30m`, `12h`, `7d` or `1d12h`.
+fn parse_duration(text: &str) -> Option<Duration> {
+	let mut total = 0u64;
+	let mut number = String::new();
+	for c in text.trim().chars() {
+		if c.is_ascii_digit() {
+			number.push(c);
+			continue;
+		}
+		let unit = match c {
+			's' => 1,
+			'm' => 60,
+			'h' => 60 * 60,
+			'd' => 24 * 60 * 60,
+			'w' => 7 * 24 * 60 * 60,
+			_ => return None,
+		};
+		total = total.checked_add(number.parse::<u64>().ok()?.checked_mul(unit)?)?;
+		number.clear();
+	}
+
+	if !number.is_empty() || total == 0 {
+		return None;
+	}
+	Some(Duration::from_secs(total))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn secs(text: &str) -> Option<u64> {
+		parse_duration(text).map(|duration| duration.as_secs())
+	}
+
+	#[test]
+	fn units_add_up() {
+		assert_eq!(secs("45s"), Some(45));
+		assert_eq!(secs("30m"), Some(30 * 60));
+		assert_eq!(secs("1d"), Some(24 * 60 * 60));
+		assert_eq!(secs("2w"), Some(14 * 24 * 60 * 60));
+		assert_eq!(secs("2h30m"), Some(2 * 60 * 60 + 30 * 60));
+		assert_eq!(secs(" 1d12h "), Some(36 * 60 * 60));
+	}
+
+	#[test]
+	fn nonsense_isnt_a_duration() {
+		assert_eq!(secs(""), None);
+		assert_eq!(secs("30"), None);
+		assert_eq!(secs("m"), None);
+		assert_eq!(secs("0m"), None);
+		assert_eq!(secs("5y"), None);
+		assert_eq!(secs("1h 30m"), None);
+		assert_eq!(secs("-5m"), None);
+	}
+
+	#[test]
+	fn overflow_isnt_a_duration() {
+		assert_eq!(secs("99999999999999999999s"), None);
+		assert_eq!(secs("18446744073709551615w"), None);
+		assert_eq!(secs("18446744073709551615s1s"), None);
+	}
+}
@@ -0,0 +1,333 @@
+use std::io::{Cursor, Read};
+use std::path::{Component, Path};
+
+use hyper::{body::HttpBody, client::HttpConnector, Client as HyperClient, Uri};
+use hyper_tls::HttpsConnector;
+use once_cell::sync::Lazy;
+use twilight_model::channel::Attachment;
+
+use crate::play::CrateFile;
+
+/// Biggest `.rs` file that gets downloaded.
+const MAX_SOURCE_BYTES: u64 = 64 * 1024;
+/// Biggest archive that gets downloaded, and the most it can unpack to.
+const MAX_ARCHIVE_BYTES: u64 = 1024 * 1024;
+const MAX_UNPACKED_BYTES: u64 = 4 * 1024 * 1024;
+const MAX_FILES: usize = 200;
+
+static CLIENT: Lazy<HyperClient<HttpsConnector<HttpConnector>>> =
+	Lazy::new(|| HyperClient::builder().build::<_, hyper::Body>(HttpsConnector::new()));
+
+/// Something uploaded to a message that can be run.
+pub enum Upload {
+	/// A single `.rs` file, run like a code block.
+	Source(String),
+	/// A crate from a `.zip` or `.tar.gz`, with its `Cargo.toml` at the root.
+	Crate(Vec<CrateFile>),
+}
+
+/// Whether an attachment looks like something that can be run, going by its
+/// name: `Some(true)` for an archive, `Some(false)` for a `.rs` file.
+fn archive(attachment: &Attachment) -> Option<bool> {
+	let name = attachment.filename.to_lowercase();
+	if name.ends_with(".zip") || name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+		Some(true)
+	} else {
+		name.ends_with(".rs").then_some(false)
+	}
+}
+
+/// How many attachments `uploads` would try to use, without downloading anything.
+pub fn runnable(attachments: &[Attachment]) -> usize {
+	attachments.iter().filter(|attachment| archive(attachment).is_some()).count()
+}
+
+/// Downloads and unpacks the attachments that can be run. Attachments that
+/// aren't rust are left out, ones that are but can't be used give a reason why.
+pub async fn uploads(attachments: &[Attachment]) -> Vec<Result<Upload, String>> {
+	let mut uploads = Vec::new();
+
+	for attachment in attachments {
+		let archive = match archive(attachment) {
+			Some(archive) => archive,
+			None => continue,
+		};
+
+		let limit = if archive { MAX_ARCHIVE_BYTES } else { MAX_SOURCE_BYTES };
+		if attachment.size > limit {
+			uploads.push(Err(format!("`{}` is too big, the most I'll take is {}KB.", attachment.filename, limit / 1024)));
+			continue;
+		}
+
+		let bytes = match download(&attachment.url, limit).await {
+			Ok(bytes) => bytes,
+			Err(why) => {
+				uploads.push(Err(format!("Couldn't download `{}`: {}", attachment.filename, why)));
+				continue;
+			}
+		};
+
+		uploads.push(if !archive {
+			String::from_utf8(bytes)
+				.map(Upload::Source)
+				.map_err(|_| format!("`{}` isn't UTF-8.", attachment.filename))
+		} else {
+			let files = if attachment.filename.to_lowercase().ends_with(".zip") { unzip(&bytes) } else { untar(&bytes) };
+			files
+				.and_then(crate_root)
+				.and_then(no_build_code)
+				.map(Upload::Crate)
+				.map_err(|why| format!("Couldn't use `{}`: {}", attachment.filename, why))
+		});
+	}
+
+	uploads
+}
+
+async fn download(url: &str, limit: u64) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+	let mut response = CLIENT.get(url.parse::<Uri>()?).await?;
+	if !response.status().is_success() {
+		return Err(format!("Discord said {}", response.status()).into());
+	}
+
+	let mut bytes = Vec::new();
+	while let Some(chunk) = response.body_mut().data().await {
+		bytes.extend_from_slice(&chunk?);
+		if bytes.len() as u64 > limit {
+			return Err("it's bigger than Discord said it was".into());
+		}
+	}
+	Ok(bytes)
+}
+
+fn unzip(bytes: &[u8]) -> Result<Vec<CrateFile>, String> {
+	let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+	let mut files = Vec::new();
+	let mut unpacked = 0;
+
+	for i in 0..archive.len() {
+		let file = archive.by_index(i).map_err(|e| e.to_string())?;
+		if file.is_dir() {
+			continue;
+		}
+
+		let path = file.name().to_string();
+		files.push(read_file(path, file, &mut unpacked, files.len())?);
+	}
+
+	Ok(files)
+}
+
+fn untar(bytes: &[u8]) -> Result<Vec<CrateFile>, String> {
+	let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(bytes));
+	let mut files = Vec::new();
+	let mut unpacked = 0;
+
+	for entry in archive.entries().map_err(|e| e.to_string())? {
+		let entry = entry.map_err(|e| e.to_string())?;
+		// Links could point anywhere, so only plain files are taken.
+		if !entry.header().entry_type().is_file() {
+			continue;
+		}
+
+		let path = entry.path().map_err(|e| e.to_string())?.to_string_lossy().into_owned();
+		files.push(read_file(path, entry, &mut unpacked, files.len())?);
+	}
+
+	Ok(files)
+}
+
+/// Reads one file out of an archive, keeping an eye on how much has been
+/// unpacked so far so a zip bomb can't take the bot down.
+fn read_file(path: String, reader: impl Read, unpacked: &mut u64, count: usize) -> Result<CrateFile, String> {
+	if count >= MAX_FILES {
+		return Err(format!("it has more than {} files in it.", MAX_FILES));
+	}
+
+	// Anything that could end up outside the directory it's unpacked in is refused.
+	if !Path::new(&path).components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+		return Err(format!("`{}` isn't a path I'll unpack.", path));
+	}
+
+	let mut contents = Vec::new();
+	reader
+		.take(MAX_UNPACKED_BYTES - *unpacked + 1)
+		.read_to_end(&mut contents)
+		.map_err(|e| e.to_string())?;
+
+	*unpacked += contents.len() as u64;
+	if *unpacked > MAX_UNPACKED_BYTES {
+		return Err(format!("it unpacks to more than {}MB.", MAX_UNPACKED_BYTES / 1024 / 1024));
+	}
+
+	Ok(CrateFile { path, contents })
+}
+
+/// Archives often have everything in a folder named after the crate, so the
+/// shallowest `Cargo.toml` is taken as the crate's root and everything else is
+/// made relative to it.
+fn crate_root(files: Vec<CrateFile>) -> Result<Vec<CrateFile>, String> {
+	let manifest = files.iter()
+		.filter(|f| f.path == "Cargo.toml" || f.path.ends_with("/Cargo.toml"))
+		.min_by_key(|f| f.path.matches('/').count())
+		.ok_or("there's no `Cargo.toml` in it.")?;
+	let root = manifest.path.trim_end_matches("Cargo.toml").to_string();
+
+	let files = files.into_iter()
+		.filter_map(|f| Some(CrateFile {
+			path: f.path.strip_prefix(&root)?.to_string(),
+			contents: f.contents,
+		}))
+		// Nobody needs their old build output sent along.
+		.filter(|f| !f.path.starts_with("target/"))
+		.collect::<Vec<_>>();
+
+	if !files.iter().any(|f| f.path.starts_with("src/")) {
+		return Err("there's no `src` folder next to its `Cargo.toml`.".to_string());
+	}
+	Ok(files)
+}
+
+/// Build scripts and proc macros run while cargo builds, where only the
+/// process cap applies, and `.cargo/config.toml` can swap out rustc itself. So
+/// crates that have any of them, or pull in code from a path, are refused.
+fn no_build_code(files: Vec<CrateFile>) -> Result<Vec<CrateFile>, String> {
+	for file in &files {
+		let path = Path::new(&file.path);
+		if path.components().any(|c| c.as_os_str() == ".cargo") {
+			return Err(format!("`{}` is cargo config, which I don't take.", file.path));
+		}
+		if path.file_name().is_none_or(|name| name != "Cargo.toml") {
+			continue;
+		}
+
+		let dir = file.path.trim_end_matches("Cargo.toml");
+		if files.iter().any(|f| f.path == format!("{}build.rs", dir)) {
+			return Err(format!("`{}build.rs` is a build script, which I don't run.", dir));
+		}
+
+		let manifest = std::str::from_utf8(&file.contents)
+			.ok()
+			.and_then(|contents| contents.parse::<toml::Value>().ok())
+			.ok_or_else(|| format!("`{}` isn't valid TOML.", file.path))?;
+		if let Some(why) = build_code(&manifest) {
+			return Err(format!("`{}` {}", file.path, why));
+		}
+	}
+	Ok(files)
+}
+
+/// Why a manifest would run code at build time, if it would.
+fn build_code(manifest: &toml::Value) -> Option<&'static str> {
+	let build = manifest.get("package").and_then(|package| package.get("build"));
+	if build.is_some_and(|build| build.as_bool() != Some(false)) {
+		return Some("has a build script, which I don't run.");
+	}
+
+	let lib = manifest.get("lib");
+	let proc_macro = ["proc-macro", "proc_macro"].iter().any(|key| {
+		lib.and_then(|lib| lib.get(key)).and_then(toml::Value::as_bool) == Some(true)
+	});
+	if proc_macro {
+		return Some("is a proc macro, which I don't build.");
+	}
+
+	if manifest.get("patch").is_some() || manifest.get("replace").is_some() {
+		return Some("patches dependencies, which I don't allow.");
+	}
+
+	// Dependencies can be listed at the top, per target or for the workspace.
+	let mut tables = vec![manifest];
+	tables.extend(manifest.get("target").and_then(toml::Value::as_table).into_iter().flat_map(|targets| targets.values()));
+	tables.extend(manifest.get("workspace"));
+	let from_path = tables.iter()
+		.flat_map(|table| ["dependencies", "dev-dependencies", "build-dependencies"].map(|key| table.get(key)))
+		.flatten()
+		.filter_map(toml::Value::as_table)
+		.flat_map(|dependencies| dependencies.values())
+		.any(|dependency| dependency.get("path").is_some());
+	if from_path {
+		return Some("has a path dependency, only ones already on this machine can be used.");
+	}
+
+	None
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn file(path: &str, contents: &str) -> CrateFile {
+		CrateFile { path: path.to_string(), contents: contents.as_bytes().to_vec() }
+	}
+
+	fn paths(files: &[CrateFile]) -> Vec<&str> {
+		files.iter().map(|f| f.path.as_str()).collect()
+	}
+
+	#[test]
+	fn read_file_refuses_paths_outside_the_crate() {
+		let mut unpacked = 0;
+		assert!(read_file(String::from("../main.rs"), &b""[..], &mut unpacked, 0).is_err());
+		assert!(read_file(String::from("src/../../main.rs"), &b""[..], &mut unpacked, 0).is_err());
+		assert!(read_file(String::from("/etc/passwd"), &b""[..], &mut unpacked, 0).is_err());
+		assert!(read_file(String::from("./src/main.rs"), &b"fn main() {}"[..], &mut unpacked, 0).is_ok());
+	}
+
+	#[test]
+	fn read_file_caps_the_unpacked_size() {
+		let mut unpacked = 0;
+		let half = vec![0; MAX_UNPACKED_BYTES as usize / 2];
+		assert!(read_file(String::from("a"), &half[..], &mut unpacked, 0).is_ok());
+		assert!(read_file(String::from("b"), &half[..], &mut unpacked, 1).is_ok());
+		assert_eq!(unpacked, MAX_UNPACKED_BYTES);
+		assert!(read_file(String::from("c"), &b"x"[..], &mut unpacked, 2).is_err());
+	}
+
+	#[test]
+	fn read_file_caps_the_file_count() {
+		let mut unpacked = 0;
+		assert!(read_file(String::from("a"), &b""[..], &mut unpacked, MAX_FILES - 1).is_ok());
+		assert!(read_file(String::from("a"), &b""[..], &mut unpacked, MAX_FILES).is_err());
+	}
+
+	#[test]
+	fn crate_root_is_the_shallowest_manifest() {
+		let files = crate_root(vec![
+			file("hello/Cargo.toml", ""),
+			file("hello/src/main.rs", ""),
+			file("hello/member/Cargo.toml", ""),
+			file("hello/target/debug/hello", ""),
+			file("README.md", ""),
+		]).unwrap();
+		assert_eq!(paths(&files), ["Cargo.toml", "src/main.rs", "member/Cargo.toml"]);
+	}
+
+	#[test]
+	fn crate_root_needs_a_manifest_and_src() {
+		assert!(crate_root(vec![file("src/main.rs", "")]).is_err());
+		assert!(crate_root(vec![file("Cargo.toml", ""), file("main.rs", "")]).is_err());
+	}
+
+	#[test]
+	fn crates_that_run_code_while_building_are_refused() {
+		let krate = |manifest: &str, extra: &[&str]| {
+			let mut files = vec![file("Cargo.toml", manifest), file("src/main.rs", "")];
+			files.extend(extra.iter().map(|path| file(path, "")));
+			no_build_code(files)
+		};
+
+		assert!(krate("[package]\nname = \"a\"\n[dependencies]\nrand = \"0.8\"", &[]).is_ok());
+		assert!(krate("[package]\nname = \"a\"\nbuild = false", &["build.rs"]).is_err());
+		assert!(krate("[package]\nname = \"a\"\nbuild = false", &[]).is_ok());
+		assert!(krate("[package]\nname = \"a\"", &["build.rs"]).is_err());
+		assert!(krate("[package]\nname = \"a\"\nbuild = \"src/make.rs\"", &[]).is_err());
+		assert!(krate("[lib]\nproc-macro = true", &[]).is_err());
+		assert!(krate("[dependencies]\nb = { path = \"b\" }", &[]).is_err());
+		assert!(krate("[target.'cfg(unix)'.dependencies]\nb = { path = \"/b\" }", &[]).is_err());
+		assert!(krate("[patch.crates-io]\nrand = { git = \"x\" }", &[]).is_err());
+		assert!(krate("[package]\nname = \"a\"", &[".cargo/config.toml"]).is_err());
+		assert!(krate("[package]\nname = \"a\"", &["member/build.rs", "member/Cargo.toml"]).is_err());
+		assert!(krate("not toml = = =", &[]).is_err());
+	}
+}
@@ -0,0 +1,153 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::error;
+use twilight_http::Client;
+use twilight_model::{
+	channel::embed::Embed,
+	datetime::Timestamp,
+	id::{marker::{ChannelMarker, GuildMarker, MessageMarker, RoleMarker, UserMarker}, Id},
+};
+use twilight_util::builder::embed::{EmbedBuilder, EmbedFieldBuilder};
+
+use crate::config::{self, ScreenAction};
+use crate::error::BotError;
+
+const ADDED: u32 = 0x57f287;
+const REMOVED: u32 = 0xed4245;
+const NOTICE: u32 = 0xfee75c;
+const INFO: u32 = 0x5865f2;
+
+/// Something the bot did (or wouldn't do) that moderators might want to know about.
+pub enum Entry {
+	/// Someone gave themselves a role with a button menu, or took it off.
+	Role {
+		user: Id<UserMarker>,
+		role: Id<RoleMarker>,
+		added: bool,
+		/// The menu's message, and the label of the button that was pressed.
+		menu: (Id<ChannelMarker>, Id<MessageMarker>),
+		label: Option<String>,
+	},
+	/// Someone tried to do something they're not allowed to, like running code while banned.
+	Refused {
+		user: Id<UserMarker>,
+		/// What they tried, like `/run`.
+		action: String,
+		reason: String,
+	},
+	Project {
+		user: Id<UserMarker>,
+		name: String,
+		/// Where it was posted, if posting it worked.
+		posted: Option<(Id<ChannelMarker>, Id<MessageMarker>)>,
+	},
+	/// An admin changed something with `/admin`.
+	Admin {
+		moderator: Id<UserMarker>,
+		change: String,
+		reason: Option<String>,
+	},
+	/// Code matched some of the guild's screening rules.
+	Screened {
+		user: Id<UserMarker>,
+		channel: Id<ChannelMarker>,
+		rules: Vec<String>,
+		/// The most drastic action of the rules, what was done about it.
+		action: ScreenAction,
+		code: String,
+	},
+}
+
+impl Entry {
+	fn embed(&self, guild: Id<GuildMarker>) -> Result<Embed, BotError> {
+		let link = |(channel, message): (Id<ChannelMarker>, Id<MessageMarker>)| {
+			format!("https://discord.com/channels/{}/{}/{}", guild, channel, message)
+		};
+
+		let embed = match self {
+			Entry::Role { user, role, added, menu, label } => EmbedBuilder::new()
+				.title(if *added { "Role added" } else { "Role removed" })
+				.color(if *added { ADDED } else { REMOVED })
+				.field(EmbedFieldBuilder::new("Member", format!("<@{}>", user)).inline())
+				.field(EmbedFieldBuilder::new("Role", format!("<@&{}>", role)).inline())
+				.field(EmbedFieldBuilder::new("Menu", match label {
+					Some(label) => format!("[{}]({})", clip(label, 200), link(*menu)),
+					None => link(*menu),
+				})),
+			Entry::Refused { user, action, reason } => EmbedBuilder::new()
+				.title("Refused")
+				.color(REMOVED)
+				.field(EmbedFieldBuilder::new("Member", format!("<@{}>", user)).inline())
+				.field(EmbedFieldBuilder::new("Tried", clip(action, 1024)).inline())
+				.field(EmbedFieldBuilder::new("Why", clip(reason, 1024))),
+			Entry::Project { user, name, posted } => {
+				let embed = EmbedBuilder::new()
+					.title("Project submitted")
+					.color(INFO)
+					.field(EmbedFieldBuilder::new("Member", format!("<@{}>", user)).inline())
+					.field(EmbedFieldBuilder::new("Name", clip(name, 1024)).inline());
+				match posted {
+					Some(posted) => embed.field(EmbedFieldBuilder::new("Post", link(*posted))),
+					None => embed.description("Posting it failed."),
+				}
+			}
+			Entry::Admin { moderator, change, reason } => {
+				let embed = EmbedBuilder::new()
+					.title("Settings changed")
+					.color(NOTICE)
+					.description(clip(change, 4096))
+					.field(EmbedFieldBuilder::new("By", format!("<@{}>", moderator)).inline());
+				match reason {
+					Some(reason) => embed.field(EmbedFieldBuilder::new("Reason", clip(reason, 1024)).inline()),
+					None => embed,
+				}
+			}
+			Entry::Screened { user, channel, rules, action, code } => EmbedBuilder::new()
+				.title(match action {
+					ScreenAction::Warn => "Code run with a warning",
+					ScreenAction::Confirm => "Code held to be confirmed",
+					ScreenAction::Refuse => "Code refused",
+				})
+				.color(if *action == ScreenAction::Refuse { REMOVED } else { NOTICE })
+				.description(format!("```rs\n{}\n```", clip(&code.replace("```", "`\u{200b}``"), 3900)))
+				.field(EmbedFieldBuilder::new("Member", format!("<@{}>", user)).inline())
+				.field(EmbedFieldBuilder::new("Channel", format!("<#{}>", channel)).inline())
+				.field(EmbedFieldBuilder::new("Matched", clip(&rules.join(", "), 1024))),
+		};
+
+		let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default();
+		let embed = match Timestamp::from_secs(now) {
+			Ok(now) => embed.timestamp(now),
+			Err(_) => embed,
+		};
+		Ok(embed.validate()?.build())
+	}
+}
+
+/// Posts an entry in the guild's `audit_channel`, if it has one. Failing to
+/// only gets logged, whatever it's about has already happened.
+pub async fn log(http: &Client, guild: Id<GuildMarker>, entry: Entry) {
+	let channel = match config::get().guild(guild).and_then(|guild| guild.audit_channel) {
+		Some(channel) => Id::<ChannelMarker>::new(channel),
+		None => return,
+	};
+
+	let sent = async {
+		http.create_message(channel)
+			.embeds(&[entry.embed(guild)?])?
+			.exec()
+			.await?;
+		Ok::<_, BotError>(())
+	};
+	if let Err(why) = sent.await {
+		error!("Failed to post in the audit channel: {}", why);
+	}
+}
+
+/// Cuts text down to fit in an embed.
+fn clip(text: &str, max: usize) -> String {
+	match text.char_indices().nth(max - 1) {
+		Some((end, _)) => format!("{}…", &text[..end]),
+		None => text.to_string(),
+	}
+}
@@ -0,0 +1,609 @@
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+	error::Error,
+	path::{Path, PathBuf},
+	process::Stdio,
+	sync::{atomic::{AtomicU64, Ordering}, Arc},
+	time::Duration,
+};
+use tokio::{io::{AsyncRead, AsyncReadExt}, process::Command};
+
+use crate::config::{BackendConfig, LocalBackendConfig, RemoteBackendConfig};
+use crate::paste;
+use crate::playground::PlaygroundClient;
+use crate::code::{Target, Tool};
+use crate::play::{CompileRequest, CompileResult, Crate, Playground, PlaygroundResult, ToolResult};
+
+pub type BackendError = Box<dyn Error + Send + Sync>;
+
+/// Something that can take a `Playground` request and run it.
+#[async_trait]
+pub trait ExecutionBackend: Send + Sync {
+	async fn execute(&self, playground: &Playground) -> Result<PlaygroundResult, BackendError>;
+
+	/// Builds and runs a whole crate with cargo, for code that's more than one file.
+	async fn execute_crate(&self, krate: &Crate) -> Result<PlaygroundResult, BackendError>;
+
+	/// Compiles the code to something other than a binary (assembly, IR...) and gives that back.
+	async fn compile(&self, request: &CompileRequest) -> Result<CompileResult, BackendError>;
+
+	/// Runs clippy, rustfmt, miri or macro expansion over the code.
+	async fn tool(&self, tool: Tool, playground: &Playground) -> Result<ToolResult, BackendError>;
+
+	/// Saves the code somewhere it can be opened again, giving back a link to it.
+	/// `None` if there's nowhere to save it.
+	async fn share(&self, playground: &Playground) -> Result<Option<String>, BackendError>;
+
+	/// Whether `share` has somewhere to save code, and when results should use it.
+	fn sharing(&self) -> Sharing;
+}
+
+/// How results link to their code.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sharing {
+	/// There's nowhere to save it, so they don't.
+	Nowhere,
+	/// Saving it makes a gist on someone else's playground, so it's only done
+	/// once someone presses "Share", most results never are.
+	OnPress,
+	/// Saving it costs nothing, so every result links straight to its code.
+	Upfront,
+}
+
+pub fn from_config(config: &BackendConfig) -> Arc<dyn ExecutionBackend> {
+	match config {
+		BackendConfig::Remote(remote) => Arc::new(RemotePlayground::new(remote)),
+		BackendConfig::Local(local) => Arc::new(LocalSandbox::new(local)),
+	}
+}
+
+/// The public playground, or anything that speaks its API.
+pub struct RemotePlayground {
+	client: PlaygroundClient,
+}
+
+impl RemotePlayground {
+	pub fn new(config: &RemoteBackendConfig) -> Self {
+		Self { client: PlaygroundClient::new(config) }
+	}
+
+	async fn post<T: Serialize + Sync, R: DeserializeOwned>(&self, path: &str, body: &T) -> Result<R, BackendError> {
+		Ok(self.client.post(path, body).await?)
+	}
+}
+
+#[async_trait]
+impl ExecutionBackend for RemotePlayground {
+	async fn execute(&self, playground: &Playground) -> Result<PlaygroundResult, BackendError> {
+		self.post("execute", playground).await
+	}
+
+	async fn execute_crate(&self, _krate: &Crate) -> Result<PlaygroundResult, BackendError> {
+		// The playground's API only ever takes a single file.
+		Ok(PlaygroundResult {
+			success: false,
+			stdout: String::new(),
+			stderr: String::from("The playground can only run single files, crates need the bot to be set up with the local backend."),
+		})
+	}
+
+	async fn compile(&self, request: &CompileRequest) -> Result<CompileResult, BackendError> {
+		self.post("compile", request).await
+	}
+
+	async fn tool(&self, tool: Tool, playground: &Playground) -> Result<ToolResult, BackendError> {
+		let path = match tool {
+			Tool::Clippy => "clippy",
+			Tool::Rustfmt => "format",
+			Tool::Miri => "miri",
+			Tool::Expand => "macro-expansion",
+		};
+		self.post(path, playground).await
+	}
+
+	async fn share(&self, playground: &Playground) -> Result<Option<String>, BackendError> {
+		let gist: Gist = self.post("meta/gist", &Gist { id: String::new(), code: playground.code.clone() }).await?;
+		Ok(Some(format!(
+			"{}/?version={}&mode={}&edition={}&gist={}",
+			self.client.url(), playground.channel, playground.mode, playground.edition, gist.id
+		)))
+	}
+
+	fn sharing(&self) -> Sharing {
+		Sharing::OnPress
+	}
+}
+
+/// The playground's gists are both what gets sent (just the code) and what comes back.
+#[derive(Serialize, Deserialize)]
+struct Gist {
+	#[serde(default, skip_serializing)]
+	id: String,
+	code: String,
+}
+
+/// Runs rustc on this machine in a throwaway directory.
+///
+/// The compiled program is run with rlimits on cpu time, memory and file size,
+/// a wall clock timeout and capped output. Everything it starts runs as the
+/// bot's user unless `run_as` is set, so it can read whatever the bot can (the
+/// token included). Even then this is not a real sandbox, it's not safe to let
+/// people you don't trust use it.
+pub struct LocalSandbox {
+	work_dir: PathBuf,
+	timeout: Duration,
+	cpu_secs: u64,
+	memory_bytes: u64,
+	max_output: usize,
+	max_processes: u64,
+	/// The uid and gid children switch to, after leaving the bot's network namespace.
+	run_as: Option<(u32, u32)>,
+	paste_url: Option<String>,
+	paste_dir: PathBuf,
+	counter: AtomicU64,
+}
+
+impl LocalSandbox {
+	pub fn new(config: &LocalBackendConfig) -> Self {
+		Self {
+			work_dir: config.work_dir.clone().map(PathBuf::from).unwrap_or_else(std::env::temp_dir),
+			timeout: Duration::from_secs(config.timeout_secs),
+			cpu_secs: config.cpu_secs,
+			memory_bytes: config.memory_mb * 1024 * 1024,
+			max_output: config.max_output_bytes,
+			max_processes: config.max_processes,
+			run_as: config.run_as_uid.zip(config.run_as_gid),
+			paste_url: config.paste_bind.as_ref().and(config.paste_url.clone()),
+			paste_dir: PathBuf::from(&config.paste_dir),
+			counter: AtomicU64::new(0),
+		}
+	}
+
+	async fn scratch_dir(&self) -> Result<PathBuf, BackendError> {
+		let dir = self.work_dir.join(format!(
+			"run-my-rust-{}-{}",
+			std::process::id(),
+			self.counter.fetch_add(1, Ordering::Relaxed)
+		));
+		tokio::fs::create_dir_all(&dir).await?;
+		if let Some((uid, gid)) = self.run_as {
+			std::os::unix::fs::chown(&dir, Some(uid), Some(gid))?;
+		}
+		Ok(dir)
+	}
+
+	async fn run_in(&self, dir: &Path, playground: &Playground) -> Result<PlaygroundResult, BackendError> {
+		let source = dir.join("main.rs");
+		let binary = dir.join("playground");
+		tokio::fs::write(&source, &playground.code).await?;
+
+		let mut rustc = Command::new("rustc");
+		rustc
+			.current_dir(dir)
+			.env("RUSTUP_TOOLCHAIN", &playground.channel)
+			.arg("--edition").arg(&playground.edition)
+			.arg("--crate-name").arg("playground")
+			.arg("-o").arg(&binary);
+
+		if playground.tests {
+			rustc.arg("--test");
+		} else {
+			rustc.arg("--crate-type").arg(&playground.crate_type);
+		}
+
+		match playground.mode.as_str() {
+			"release" => rustc.args(["-C", "opt-level=3"]),
+			_ => rustc.args(["-C", "debuginfo=2"]),
+		};
+		rustc.arg("main.rs");
+
+		let compile = self.spawn_capped(rustc, Limits::Toolchain).await?;
+		let mut stderr = compile.stderr;
+
+		if !compile.success {
+			return Ok(PlaygroundResult { success: false, stdout: compile.stdout, stderr });
+		}
+
+		// Libraries don't have anything to run, compiling them is the whole point.
+		if playground.crate_type == "lib" && !playground.tests {
+			return Ok(PlaygroundResult { success: true, stdout: compile.stdout, stderr });
+		}
+
+		let mut program = Command::new(&binary);
+		program.current_dir(dir);
+		program.env("RUST_BACKTRACE", if playground.backtrace { "1" } else { "0" });
+
+		let run = self.spawn_capped(program, Limits::Program).await?;
+		stderr.push_str(&run.stderr);
+
+		Ok(PlaygroundResult { success: run.success, stdout: run.stdout, stderr })
+	}
+
+	async fn crate_in(&self, dir: &Path, krate: &Crate) -> Result<PlaygroundResult, BackendError> {
+		for file in &krate.files {
+			let path = dir.join(&file.path);
+			if let Some(parent) = path.parent() {
+				tokio::fs::create_dir_all(parent).await?;
+			}
+			tokio::fs::write(path, &file.contents).await?;
+		}
+
+		let mut cargo = Command::new("cargo");
+		cargo
+			.current_dir(dir)
+			.env("RUSTUP_TOOLCHAIN", &krate.channel)
+			.env("CARGO_TARGET_DIR", dir.join("target"))
+			.env("RUST_BACKTRACE", if krate.backtrace { "1" } else { "0" })
+			.arg(if krate.tests { "test" } else { "run" })
+			// Only dependencies already on this machine can be used.
+			.args(["--offline", "--quiet"]);
+
+		if krate.release {
+			cargo.arg("--release");
+		}
+		if krate.tests {
+			// Doctests are run by rustdoc, not the runner below, so they're left out.
+			cargo.args(["--lib", "--bins", "--tests"]);
+		}
+
+		// Cargo hands the program (or the tests) to prlimit to run, so it gets
+		// the same rlimits as single files do. Cargo itself only gets the process
+		// cap, which is why crates with build scripts or proc macros are refused
+		// when they're uploaded.
+		let runner = [
+			String::from("prlimit"),
+			format!("--cpu={}", self.cpu_secs),
+			format!("--as={}", self.memory_bytes),
+			format!("--fsize={}", self.max_output),
+			format!("--nproc={}", self.max_processes),
+			String::from("--"),
+		];
+		cargo.arg("--config").arg(format!("target.'cfg(all())'.runner = {:?}", runner));
+
+		self.spawn_capped(cargo, Limits::Toolchain).await
+	}
+
+	async fn compile_in(&self, dir: &Path, request: &CompileRequest) -> Result<CompileResult, BackendError> {
+		tokio::fs::write(dir.join("main.rs"), &request.code).await?;
+		let output = dir.join("output");
+		let target = Target::parse(&request.target)?;
+
+		let mut rustc = Command::new("rustc");
+		rustc
+			.current_dir(dir)
+			.env("RUSTUP_TOOLCHAIN", &request.channel)
+			.arg("--edition").arg(&request.edition)
+			.arg("--crate-name").arg("playground")
+			.arg("--crate-type").arg(&request.crate_type);
+
+		if request.mode == "release" {
+			rustc.args(["-C", "opt-level=3"]);
+		}
+
+		match target {
+			Target::Asm => rustc.args(["-C", "llvm-args=-x86-asm-syntax=intel"]).arg(emit("asm", &output)),
+			Target::LlvmIr => rustc.arg(emit("llvm-ir", &output)),
+			Target::Mir => rustc.arg(emit("mir", &output)),
+			// This one goes to stdout rather than a file.
+			Target::Hir => rustc.arg("-Zunpretty=hir"),
+			Target::Wasm => rustc.args(["--target", "wasm32-unknown-unknown"]).arg(emit("asm", &output)),
+		};
+		rustc.arg("main.rs");
+
+		let compiled = self.spawn_capped(rustc, Limits::Toolchain).await?;
+		let code = match target {
+			Target::Hir => compiled.stdout.clone(),
+			_ if compiled.success => tokio::fs::read_to_string(&output).await.unwrap_or_default(),
+			_ => String::new(),
+		};
+
+		Ok(CompileResult { success: compiled.success, code, stdout: String::new(), stderr: compiled.stderr })
+	}
+
+	async fn tool_in(&self, dir: &Path, tool: Tool, playground: &Playground) -> Result<ToolResult, BackendError> {
+		tokio::fs::write(dir.join("main.rs"), &playground.code).await?;
+
+		let mut command = match tool {
+			Tool::Clippy => {
+				let mut clippy = Command::new("clippy-driver");
+				clippy
+					.env("RUSTUP_TOOLCHAIN", &playground.channel)
+					.arg("--crate-type").arg(&playground.crate_type)
+					.arg("--emit=metadata");
+				clippy
+			}
+			Tool::Rustfmt => {
+				let mut rustfmt = Command::new("rustfmt");
+				rustfmt.env("RUSTUP_TOOLCHAIN", &playground.channel);
+				rustfmt
+			}
+			Tool::Miri => {
+				// Miri wants a real cargo project to run in.
+				tokio::fs::create_dir_all(dir.join("src")).await?;
+				tokio::fs::rename(dir.join("main.rs"), dir.join("src/main.rs")).await?;
+				tokio::fs::write(dir.join("Cargo.toml"), format!(
+					"[package]\nname = \"playground\"\nversion = \"0.0.0\"\nedition = \"{}\"\n",
+					playground.edition
+				)).await?;
+
+				let mut miri = Command::new("cargo");
+				miri.env("RUSTUP_TOOLCHAIN", "nightly").args(["miri", "run", "--quiet"]);
+				miri
+			}
+			Tool::Expand => {
+				let mut rustc = Command::new("rustc");
+				rustc
+					.env("RUSTUP_TOOLCHAIN", "nightly")
+					.arg("--crate-type").arg(&playground.crate_type)
+					.arg("-Zunpretty=expanded");
+				rustc
+			}
+		};
+
+		command.current_dir(dir);
+		if tool != Tool::Miri {
+			command.arg("--edition").arg(&playground.edition).arg("main.rs");
+		}
+
+		// Miri interprets the code rather than running it, and cargo needs more room
+		// than run code gets, so the timeout and the process cap are enough here.
+		let output = self.spawn_capped(command, Limits::Toolchain).await?;
+		let code = match tool {
+			Tool::Rustfmt if output.success => tokio::fs::read_to_string(dir.join("main.rs")).await?,
+			_ => String::new(),
+		};
+
+		Ok(ToolResult { success: output.success, code, stdout: output.stdout, stderr: output.stderr })
+	}
+
+	async fn spawn_capped(&self, mut command: Command, limits: Limits) -> Result<PlaygroundResult, BackendError> {
+		// Nothing from the bot's own environment gets through (the token could be
+		// in there), only what was set for this command and what the toolchain needs.
+		let set = command.as_std()
+			.get_envs()
+			.filter_map(|(key, value)| Some((key.to_owned(), value?.to_owned())))
+			.collect::<Vec<_>>();
+		command.env_clear();
+		for key in PASSED_ENV {
+			if let Some(value) = std::env::var_os(key) {
+				command.env(key, value);
+			}
+		}
+		command.envs(set);
+
+		command
+			.stdin(Stdio::null())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::piped())
+			.kill_on_drop(true);
+
+		let (cpu, memory, file_size, processes) = (self.cpu_secs, self.memory_bytes, self.max_output as u64, self.max_processes);
+		let run_as = self.run_as;
+		// SAFETY: only async-signal-safe calls (unshare, set*id, setsid, setrlimit)
+		// happen between fork and exec.
+		unsafe {
+			command.pre_exec(move || {
+				if let Some((uid, gid)) = run_as {
+					// No network, and a user that can't read the bot's /proc or its files.
+					check(libc::unshare(libc::CLONE_NEWNET))?;
+					check(libc::setgroups(0, std::ptr::null()))?;
+					check(libc::setgid(gid))?;
+					check(libc::setuid(uid))?;
+				}
+				// Its own process group, so whatever it starts can be killed along with it.
+				check(libc::setsid())?;
+				set_limit(libc::RLIMIT_NPROC, processes)?;
+				if limits == Limits::Program {
+					set_limit(libc::RLIMIT_CPU, cpu)?;
+					set_limit(libc::RLIMIT_AS, memory)?;
+					set_limit(libc::RLIMIT_FSIZE, file_size)?;
+				}
+				Ok(())
+			});
+		}
+
+		let mut child = command.spawn()?;
+		// Killed once it's done or timed out, for anything it left behind (like the
+		// program `cargo run` started).
+		let _group = child.id().map(ProcessGroup);
+		let stdout = child.stdout.take().ok_or("child has no stdout")?;
+		let stderr = child.stderr.take().ok_or("child has no stderr")?;
+		let max_output = self.max_output;
+
+		let finished = tokio::time::timeout(self.timeout, async {
+			let (stdout, stderr) = tokio::join!(read_capped(stdout, max_output), read_capped(stderr, max_output));
+			(stdout, stderr, child.wait().await)
+		}).await;
+
+		match finished {
+			Ok((stdout, stderr, status)) => Ok(PlaygroundResult {
+				success: status?.success(),
+				stdout: stdout?,
+				stderr: stderr?,
+			}),
+			Err(_) => Ok(PlaygroundResult {
+				success: false,
+				stdout: String::new(),
+				stderr: format!("Timed out after {} seconds and was killed.", self.timeout.as_secs()),
+			}),
+		}
+	}
+}
+
+#[async_trait]
+impl ExecutionBackend for LocalSandbox {
+	async fn execute(&self, playground: &Playground) -> Result<PlaygroundResult, BackendError> {
+		let dir = self.scratch_dir().await?;
+		let result = self.run_in(&dir, playground).await;
+		let _ = tokio::fs::remove_dir_all(&dir).await;
+
+		result
+	}
+
+	async fn execute_crate(&self, krate: &Crate) -> Result<PlaygroundResult, BackendError> {
+		let dir = self.scratch_dir().await?;
+		let result = self.crate_in(&dir, krate).await;
+		let _ = tokio::fs::remove_dir_all(&dir).await;
+
+		result
+	}
+
+	async fn compile(&self, request: &CompileRequest) -> Result<CompileResult, BackendError> {
+		let dir = self.scratch_dir().await?;
+		let result = self.compile_in(&dir, request).await;
+		let _ = tokio::fs::remove_dir_all(&dir).await;
+
+		result
+	}
+
+	async fn tool(&self, tool: Tool, playground: &Playground) -> Result<ToolResult, BackendError> {
+		let dir = self.scratch_dir().await?;
+		let result = self.tool_in(&dir, tool, playground).await;
+		let _ = tokio::fs::remove_dir_all(&dir).await;
+
+		result
+	}
+
+	async fn share(&self, playground: &Playground) -> Result<Option<String>, BackendError> {
+		let url = match &self.paste_url {
+			Some(url) => url.trim_end_matches('/'),
+			None => return Ok(None),
+		};
+
+		let id = paste::save(&self.paste_dir, &playground.code).await?;
+		Ok(Some(format!("{}/{}", url, id)))
+	}
+
+	fn sharing(&self) -> Sharing {
+		if self.paste_url.is_some() { Sharing::Upfront } else { Sharing::Nowhere }
+	}
+}
+
+/// Which rlimits a child gets.
+#[derive(Clone, Copy, PartialEq)]
+enum Limits {
+	/// Compilers, cargo and miri, which need more room than run code gets. Only
+	/// how many processes they start is capped, past that it's the timeout.
+	Toolchain,
+	/// Run code, which gets all of them.
+	Program,
+}
+
+/// A child's process group, which gets killed when this is dropped.
+struct ProcessGroup(u32);
+
+impl Drop for ProcessGroup {
+	fn drop(&mut self) {
+		// SAFETY: killpg doesn't touch memory, at worst the group is already gone.
+		unsafe {
+			libc::killpg(self.0 as libc::pid_t, libc::SIGKILL);
+		}
+	}
+}
+
+/// The only environment variables run code and the toolchain get from the bot's.
+const PASSED_ENV: [&str; 5] = ["PATH", "HOME", "RUSTUP_HOME", "CARGO_HOME", "RUSTUP_TOOLCHAIN"];
+
+/// A backend for tests, giving back whatever it's told to and remembering the
+/// code it was asked about, so nothing has to be compiled or sent anywhere.
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakeBackend {
+	/// What every run, compile and tool gives back: whether it worked, stdout
+	/// and stderr. Without it they all fail like the playground being down.
+	pub output: Option<(bool, String, String)>,
+	/// The link `share` gives back.
+	pub share: Option<String>,
+	/// Whether results link to `share` straight away rather than with a "Share" button.
+	pub upfront: bool,
+	pub seen: std::sync::Mutex<Vec<String>>,
+}
+
+#[cfg(test)]
+impl FakeBackend {
+	fn answer(&self, code: &str) -> Result<(bool, String, String), BackendError> {
+		self.seen.lock().unwrap().push(code.to_string());
+		self.output.clone().ok_or_else(|| "the playground is down".into())
+	}
+}
+
+#[cfg(test)]
+#[async_trait]
+impl ExecutionBackend for FakeBackend {
+	async fn execute(&self, playground: &Playground) -> Result<PlaygroundResult, BackendError> {
+		let (success, stdout, stderr) = self.answer(&playground.code)?;
+		Ok(PlaygroundResult { success, stdout, stderr })
+	}
+
+	async fn execute_crate(&self, krate: &Crate) -> Result<PlaygroundResult, BackendError> {
+		let files = krate.files.iter().map(|file| file.path.as_str()).collect::<Vec<_>>();
+		let (success, stdout, stderr) = self.answer(&files.join(","))?;
+		Ok(PlaygroundResult { success, stdout, stderr })
+	}
+
+	async fn compile(&self, request: &CompileRequest) -> Result<CompileResult, BackendError> {
+		let (success, code, stderr) = self.answer(&request.code)?;
+		Ok(CompileResult { success, code, stdout: String::new(), stderr })
+	}
+
+	async fn tool(&self, _tool: Tool, playground: &Playground) -> Result<ToolResult, BackendError> {
+		let (success, stdout, stderr) = self.answer(&playground.code)?;
+		Ok(ToolResult { success, code: stdout.clone(), stdout, stderr })
+	}
+
+	async fn share(&self, _playground: &Playground) -> Result<Option<String>, BackendError> {
+		Ok(self.share.clone())
+	}
+
+	fn sharing(&self) -> Sharing {
+		match (&self.share, self.upfront) {
+			(None, _) => Sharing::Nowhere,
+			(Some(_), false) => Sharing::OnPress,
+			(Some(_), true) => Sharing::Upfront,
+		}
+	}
+}
+
+fn emit(kind: &str, output: &Path) -> String {
+	format!("--emit={}={}", kind, output.display())
+}
+
+fn set_limit(resource: libc::__rlimit_resource_t, value: u64) -> std::io::Result<()> {
+	let limit = libc::rlimit { rlim_cur: value, rlim_max: value };
+	check(unsafe { libc::setrlimit(resource, &limit) })
+}
+
+/// Turns a libc return value into the last OS error if it's -1.
+fn check(result: libc::c_int) -> std::io::Result<()> {
+	if result == -1 {
+		return Err(std::io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+/// Reads everything from `reader`, keeping at most `max` bytes of it.
+async fn read_capped(mut reader: impl AsyncRead + Unpin, max: usize) -> std::io::Result<String> {
+	let mut kept = Vec::new();
+	let mut buf = [0u8; 8192];
+	let mut truncated = false;
+
+	loop {
+		let read = reader.read(&mut buf).await?;
+		if read == 0 {
+			break;
+		}
+
+		let room = max.saturating_sub(kept.len());
+		if room < read {
+			truncated = true;
+		}
+		kept.extend_from_slice(&buf[..read.min(room)]);
+	}
+
+	let mut output = String::from_utf8_lossy(&kept).into_owned();
+	if truncated {
+		output.push_str("\n... output truncated");
+	}
+	Ok(output)
+}
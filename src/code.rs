@@ -0,0 +1,347 @@
+use std::fmt;
+
+/// Languages that count as rust, after being lowercased.
+const RUST: &[&str] = &["rust", "rs"];
+
+/// How many blocks from one message get run, so one message can't queue up dozens.
+pub const MAX_BLOCKS: usize = 5;
+
+/// A rust code block pulled out of a message, along with whatever came after
+/// the language in its info string (```` ```rust,nightly,release ````).
+#[derive(Debug)]
+pub struct CodeBlock<'a> {
+	pub flags: Vec<&'a str>,
+	pub code: String,
+}
+
+/// Gets every rust code block in a message, fenced with backticks or tildes.
+///
+/// Each block is run on its own, except ones annotated with `concat`, which are
+/// glued onto the block before them (keeping its flags), and ones annotated with
+/// `ignore`, which are left out like rustdoc does.
+pub fn blocks(message: &str) -> Vec<CodeBlock<'_>> {
+	let mut blocks: Vec<CodeBlock> = Vec::new();
+
+	for (info, code) in fences(message) {
+		let mut info = info.split(|c: char| c == ',' || c.is_whitespace()).filter(|f| !f.is_empty());
+		if !info.next().is_some_and(|lang| RUST.contains(&lang.to_lowercase().as_str())) {
+			continue;
+		}
+
+		let flags = info.collect::<Vec<_>>();
+		if flags.contains(&"ignore") {
+			continue;
+		}
+
+		match blocks.last_mut() {
+			Some(previous) if flags.contains(&"concat") => {
+				previous.code.push('\n');
+				previous.code.push_str(&code);
+			}
+			_ => blocks.push(CodeBlock {
+				flags: flags.into_iter().filter(|f| *f != "concat").collect(),
+				code,
+			}),
+		}
+	}
+
+	blocks
+}
+
+/// Finds fenced code blocks, giving their info strings and contents.
+///
+/// Follows CommonMark (closing fences need the same character and at least as
+/// many of them) with the one thing Discord adds, the closing fence being
+/// allowed at the end of the last line of code.
+fn fences(message: &str) -> Vec<(&str, String)> {
+	let mut found = Vec::new();
+	let mut lines = message.lines();
+
+	while let Some(line) = lines.next() {
+		let line = line.trim_start();
+		let fence_char = match line.chars().next() {
+			Some(c @ ('`' | '~')) => c,
+			_ => continue,
+		};
+		let fence_len = line.chars().take_while(|&c| c == fence_char).count();
+		if fence_len < 3 {
+			continue;
+		}
+
+		let info = &line[fence_len..];
+		// ```like this``` on one line has no language to tell it's rust.
+		if fence_char == '`' && info.contains('`') {
+			continue;
+		}
+
+		let mut code = Vec::new();
+		for line in lines.by_ref() {
+			let trimmed = line.trim_end();
+			let closing = trimmed.chars().rev().take_while(|&c| c == fence_char).count();
+
+			if closing >= fence_len {
+				let before = &trimmed[..trimmed.len() - closing];
+				if !before.trim().is_empty() {
+					code.push(before);
+				}
+				break;
+			}
+			code.push(line);
+		}
+
+		found.push((info.trim(), code.join("\n")));
+	}
+
+	found
+}
+
+/// Whether `code` is only items (functions, structs, impls...), with nothing
+/// that could be an expression to print. Those get compiled as a library.
+pub fn only_items(code: &str) -> bool {
+	const ITEMS: &[&str] = &[
+		"fn ", "pub ", "struct ", "enum ", "union ", "impl", "trait ", "mod ", "use ", "const ",
+		"static ", "type ", "extern ", "unsafe ", "async ", "macro_rules!", "#[", "#!", "}", "//",
+	];
+
+	let mut top_level = code.lines()
+		.filter(|line| !line.trim().is_empty() && !line.starts_with(char::is_whitespace))
+		.peekable();
+
+	top_level.peek().is_some() && top_level.all(|line| ITEMS.iter().any(|item| line.starts_with(item)))
+}
+
+/// How a snippet should be built. Maps onto the playground's fields of the same names.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RunOptions {
+	pub channel: Channel,
+	pub mode: Mode,
+	pub edition: Edition,
+	pub backtrace: bool,
+	/// Something to run over the code instead of running it.
+	pub tool: Option<Tool>,
+}
+
+impl Default for RunOptions {
+	fn default() -> Self {
+		Self { channel: Channel::Stable, mode: Mode::Debug, edition: Edition::E2021, backtrace: false, tool: None }
+	}
+}
+
+impl RunOptions {
+	/// Parses code block flags. Flags can either be bare (`nightly`, `release`, `2018`, `backtrace`, `clippy`)
+	/// or `key=value` (`channel=nightly`, `mode=release`, `edition=2018`, `backtrace=true`, `tool=clippy`).
+	pub fn from_flags<'a>(flags: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+		let mut options = RunOptions::default();
+		for flag in flags {
+			options.set(flag)?;
+		}
+		Ok(options)
+	}
+
+	pub fn set(&mut self, flag: &str) -> Result<(), String> {
+		match flag.split_once('=') {
+			Some(("channel", value)) => self.channel = Channel::parse(value)?,
+			Some(("mode", value)) => self.mode = Mode::parse(value)?,
+			Some(("edition", value)) => self.edition = Edition::parse(value)?,
+			Some(("backtrace", value)) => self.backtrace = match value {
+				"true" | "yes" | "on" => true,
+				"false" | "no" | "off" => false,
+				_ => return Err(format!("Unknown backtrace setting `{}`, expected true or false.", value)),
+			},
+			Some(("tool", "run")) => self.tool = None,
+			Some(("tool", value)) => self.tool = Some(Tool::parse(value)?),
+			Some((key, _)) => return Err(format!("Unknown option `{}`. Options are `channel`, `mode`, `edition`, `backtrace` and `tool`.", key)),
+			None => {
+				if flag == "backtrace" {
+					self.backtrace = true;
+				} else if let Ok(channel) = Channel::parse(flag) {
+					self.channel = channel;
+				} else if let Ok(mode) = Mode::parse(flag) {
+					self.mode = mode;
+				} else if let Ok(edition) = Edition::parse(flag) {
+					self.edition = edition;
+				} else if let Ok(tool) = Tool::parse(flag) {
+					self.tool = Some(tool);
+				} else {
+					return Err(format!(
+						"Unknown flag `{}`. Try a channel ({}), a mode ({}), an edition ({}), a tool ({}) or `backtrace`.",
+						flag,
+						Channel::ALL.map(|c| c.as_str()).join(", "),
+						Mode::ALL.map(|m| m.as_str()).join(", "),
+						Edition::ALL.map(|e| e.as_str()).join(", "),
+						Tool::ALL.map(|t| t.as_str()).join(", "),
+					));
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// The options as flags, the same way they'd be written after a code block.
+	pub fn to_flags(self) -> String {
+		let mut flags = format!("{},{},{}", self.channel, self.mode, self.edition);
+		if self.backtrace {
+			flags.push_str(",backtrace");
+		}
+		if let Some(tool) = self.tool {
+			flags.push_str(&format!(",{}", tool));
+		}
+		flags
+	}
+}
+
+macro_rules! run_option {
+	($name:ident, $what:literal, { $($variant:ident => $value:literal),+ $(,)? }) => {
+		#[derive(Debug, Clone, Copy, PartialEq)]
+		pub enum $name {
+			$($variant),+
+		}
+
+		impl $name {
+			pub const ALL: [$name; [$($value),+].len()] = [$($name::$variant),+];
+
+			pub fn as_str(&self) -> &'static str {
+				match self {
+					$($name::$variant => $value),+
+				}
+			}
+
+			pub fn parse(value: &str) -> Result<Self, String> {
+				match value {
+					$($value => Ok($name::$variant),)+
+					_ => Err(format!(
+						"Unknown {} `{}`, expected one of {}.",
+						$what,
+						value,
+						Self::ALL.map(|v| v.as_str()).join(", ")
+					)),
+				}
+			}
+		}
+
+		impl fmt::Display for $name {
+			fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+				f.write_str(self.as_str())
+			}
+		}
+	};
+}
+
+run_option!(Channel, "channel", { Stable => "stable", Beta => "beta", Nightly => "nightly" });
+run_option!(Mode, "mode", { Debug => "debug", Release => "release", Test => "test" });
+run_option!(Edition, "edition", { E2015 => "2015", E2018 => "2018", E2021 => "2021" });
+run_option!(Tool, "tool", { Clippy => "clippy", Rustfmt => "rustfmt", Miri => "miri", Expand => "expand" });
+run_option!(Target, "target", { Asm => "asm", LlvmIr => "llvm-ir", Mir => "mir", Hir => "hir", Wasm => "wasm" });
+
+impl Target {
+	/// What to show people, `/llvm-ir` is a command name but not something to write in a sentence.
+	pub fn label(&self) -> &'static str {
+		match self {
+			Target::Asm => "assembly",
+			Target::LlvmIr => "LLVM IR",
+			Target::Mir => "MIR",
+			Target::Hir => "HIR",
+			Target::Wasm => "WebAssembly",
+		}
+	}
+
+	/// WebAssembly comes back as the compiler's assembly for it, not WAT.
+	pub fn extension(&self) -> &'static str {
+		match self {
+			Target::Asm => "s",
+			Target::LlvmIr => "ll",
+			Target::Mir => "mir",
+			Target::Hir => "hir.rs",
+			Target::Wasm => "wasm.s",
+		}
+	}
+
+	/// Context menu commands have to fit in Discord's five per guild, along with `run-message`.
+	pub fn has_message_command(&self) -> bool {
+		!matches!(self, Target::Hir)
+	}
+
+	/// Gets the target for a `/asm` style slash command or a `asm-message` context menu command.
+	pub fn from_command(name: &str) -> Option<Target> {
+		Target::parse(name.strip_suffix("-message").unwrap_or(name)).ok()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn codes(message: &str) -> Vec<(Vec<&str>, String)> {
+		blocks(message).into_iter().map(|block| (block.flags, block.code)).collect()
+	}
+
+	#[test]
+	fn finds_every_rust_block() {
+		let message = "first\n```rust\nfn main() {}\n```\nthen\n```rs,nightly\nlet x = 1;\n```";
+		assert_eq!(codes(message), [
+			(vec![], String::from("fn main() {}")),
+			(vec!["nightly"], String::from("let x = 1;")),
+		]);
+	}
+
+	#[test]
+	fn skips_other_languages_and_inline_code() {
+		let message = "```py\nprint()\n```\n```rust fn main() {}```\n```\nno language\n```";
+		assert!(blocks(message).is_empty());
+	}
+
+	#[test]
+	fn tildes_and_longer_fences() {
+		let message = "~~~rust\nlet a = 1;\n~~~\n````Rust\nlet s = \"```\";\n````";
+		assert_eq!(codes(message), [
+			(vec![], String::from("let a = 1;")),
+			(vec![], String::from("let s = \"```\";")),
+		]);
+	}
+
+	#[test]
+	fn closing_fence_on_the_last_line() {
+		assert_eq!(codes("```rust\nfn main() {}```"), [(vec![], String::from("fn main() {}"))]);
+	}
+
+	#[test]
+	fn concat_and_ignore() {
+		let message = "```rust,release\nstruct A;\n```\n```rust,ignore\nbroken\n```\n```rust,concat\nfn main() {}\n```";
+		assert_eq!(codes(message), [(vec!["release"], String::from("struct A;\nfn main() {}"))]);
+	}
+
+	#[test]
+	fn bare_and_key_value_flags() {
+		let bare = RunOptions::from_flags(["nightly", "release", "2018", "backtrace", "clippy"]).unwrap();
+		let keyed = RunOptions::from_flags([
+			"channel=nightly", "mode=release", "edition=2018", "backtrace=true", "tool=clippy",
+		]).unwrap();
+		assert_eq!(bare, keyed);
+		assert_eq!(bare.channel, Channel::Nightly);
+		assert_eq!(bare.mode, Mode::Release);
+		assert_eq!(bare.edition, Edition::E2018);
+		assert!(bare.backtrace);
+		assert_eq!(bare.tool, Some(Tool::Clippy));
+	}
+
+	#[test]
+	fn no_flags_is_the_default() {
+		assert_eq!(RunOptions::from_flags([]).unwrap(), RunOptions::default());
+		assert_eq!(RunOptions::from_flags(["clippy", "tool=run"]).unwrap().tool, None);
+	}
+
+	#[test]
+	fn unknown_flags_are_errors() {
+		assert!(RunOptions::from_flags(["fast"]).unwrap_err().contains("`fast`"));
+		assert!(RunOptions::from_flags(["channel=unstable"]).unwrap_err().contains("`unstable`"));
+		assert!(RunOptions::from_flags(["colour=red"]).unwrap_err().contains("`colour`"));
+		assert!(RunOptions::from_flags(["backtrace=maybe"]).is_err());
+	}
+
+	#[test]
+	fn flags_round_trip() {
+		let options = RunOptions::from_flags(["beta", "test", "2015", "backtrace", "miri"]).unwrap();
+		assert_eq!(options.to_flags(), "beta,test,2015,backtrace,miri");
+		assert_eq!(RunOptions::from_flags(options.to_flags().split(',')).unwrap(), options);
+	}
+}
@@ -0,0 +1,372 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use twilight_http::Client;
+use twilight_model::{
+	application::{
+		command::{ChoiceCommandOptionData, Command, CommandOption, CommandType},
+		component::{action_row::ActionRow, text_input::TextInputStyle, Component, TextInput},
+		interaction::{application_command::CommandOptionValue, modal::ModalSubmitInteraction, ApplicationCommand},
+	},
+	channel::Message,
+	http::{
+		attachment::Attachment,
+		interaction::{InteractionResponse, InteractionResponseData, InteractionResponseType},
+	},
+	id::{marker::ApplicationMarker, Id},
+};
+use twilight_util::builder::command::CommandBuilder;
+
+use crate::backend::ExecutionBackend;
+use crate::code::{self, RunOptions, Target};
+use crate::config;
+use crate::error::BotError;
+use crate::handler::{Context, Invoker, MessageCommand, ModalHandler, SlashCommand};
+use crate::play::{CompileRequest, Playground};
+use crate::queue;
+use crate::render;
+use crate::run;
+use crate::screen;
+
+/// `/asm`, `/mir` and friends. Opens a modal for the code, with the options
+/// picked riding along in its id as `compile:asm:nightly,release,2021:symbol`.
+/// The symbol goes last so it can have colons in it (`Point::new`).
+pub struct CompileCommand(pub Target);
+
+/// The longest custom id Discord takes.
+const MAX_CUSTOM_ID: usize = 100;
+
+#[async_trait]
+impl SlashCommand for CompileCommand {
+	fn command(&self) -> Command {
+		CommandBuilder::new(
+			self.0.to_string(),
+			format!("Show the {} some Rust compiles to.", self.0.label()),
+			CommandType::ChatInput,
+		)
+		.option(CommandOption::String(ChoiceCommandOptionData {
+			autocomplete: false,
+			choices: vec![],
+			description: String::from("Only show functions with this in their name."),
+			name: String::from("symbol"),
+			required: false,
+		}))
+		.option(run::choice_option("channel", "Which release channel to use. Defaults to stable.", &code::Channel::ALL.map(|c| c.as_str())))
+		.option(run::choice_option("mode", "Build in debug or release mode. Defaults to debug.", &["debug", "release"]))
+		.option(run::choice_option("edition", "Which edition to use. Defaults to 2021.", &code::Edition::ALL.map(|e| e.as_str())))
+		.build()
+	}
+
+	async fn run(&self, ctx: &Context, cmd: &ApplicationCommand, _invoker: &Invoker) -> Result<(), BotError> {
+		let mut options = RunOptions::default();
+		let mut symbol = String::new();
+
+		for option in &cmd.data.options {
+			if let CommandOptionValue::String(value) = &option.value {
+				if option.name == "symbol" {
+					symbol = value.clone();
+				} else {
+					options.set(&format!("{}={}", option.name, value)).map_err(BotError::Validation)?;
+				}
+			}
+		}
+
+		let custom_id = format!("compile:{}:{}:{}", self.0, options.to_flags(), symbol);
+		if custom_id.len() > MAX_CUSTOM_ID {
+			return Err(BotError::Validation(format!(
+				"That symbol's too long, try a shorter part of it (up to {} characters).",
+				MAX_CUSTOM_ID - (custom_id.len() - symbol.len())
+			)));
+		}
+
+		ctx.interaction()
+			.create_response(
+				cmd.id,
+				&cmd.token,
+				&InteractionResponse {
+					kind: InteractionResponseType::Modal,
+					data: Some(InteractionResponseData {
+						components: Some(vec![
+							Component::ActionRow(ActionRow {
+								components: vec![
+									Component::TextInput(TextInput {
+										custom_id: String::from("code-to-compile"),
+										label: String::from("Code to compile"),
+										max_length: None,
+										min_length: None,
+										placeholder: None,
+										required: Some(true),
+										style: TextInputStyle::Paragraph,
+										value: None
+									})
+								]
+							})
+						]),
+						custom_id: Some(custom_id),
+						title: Some(format!("Show the {}", self.0.label())),
+						..Default::default()
+					})
+				}
+			)
+			.exec()
+			.await?;
+
+		Ok(())
+	}
+}
+
+/// The `compile:...` modal from `CompileCommand` being submitted.
+pub struct CompileModal;
+
+#[async_trait]
+impl ModalHandler for CompileModal {
+	fn handles(&self, custom_id: &str) -> bool {
+		custom_id.starts_with("compile:")
+	}
+
+	async fn run(&self, ctx: &Context, modal: &ModalSubmitInteraction, invoker: &Invoker) -> Result<(), BotError> {
+		let mut parts = modal.data.custom_id.splitn(4, ':').skip(1);
+		let (target, flags, symbol) = match (parts.next().and_then(Target::from_command), parts.next(), parts.next()) {
+			(Some(target), Some(flags), Some(symbol)) => (target, flags, symbol),
+			_ => return Ok(()),
+		};
+		let options = RunOptions::from_flags(flags.split(',').filter(|f| !f.is_empty())).map_err(BotError::Validation)?;
+
+		let code = modal.data.components.iter()
+			.flat_map(|row| &row.components)
+			.find(|comp| comp.custom_id == "code-to-compile")
+			.map(|comp| comp.value.as_str());
+
+		if let Some(code) = code {
+			// Compiling doesn't run anything, but "Run anyway" would, so there's no confirming here.
+			run::pay(invoker, modal.channel_id, 1)?;
+			if !screen::check_interaction(ctx, modal.id, &modal.token, invoker, modal.channel_id, code, None).await? {
+				return Ok(());
+			}
+
+			let _permit = queue::wait_for_interaction(&ctx.http, &modal.token, invoker.user.id).await?;
+			let request = CompileRequest::new(target, Playground::new(code, &options));
+			compile(&ctx.http, &ctx.backend, &modal.token, &request, Some(symbol).filter(|s| !s.is_empty())).await?;
+		}
+
+		Ok(())
+	}
+}
+
+/// The `asm-message` style context menu commands, for the first code block in a message.
+pub struct CompileMessage(pub Target);
+
+#[async_trait]
+impl MessageCommand for CompileMessage {
+	fn name(&self) -> String {
+		format!("{}-message", self.0)
+	}
+
+	async fn run(&self, ctx: &Context, cmd: &ApplicationCommand, invoker: &Invoker, message: &Message) -> Result<(), BotError> {
+		let block = code::blocks(&message.content).into_iter().next()
+			.ok_or_else(|| BotError::Validation(String::from("That message doesn't have a ```rust code block in it.")))?;
+		let options = RunOptions::from_flags(block.flags).map_err(BotError::Validation)?;
+
+		run::pay(invoker, cmd.channel_id, 1)?;
+		if !screen::check_interaction(ctx, cmd.id, &cmd.token, invoker, cmd.channel_id, &block.code, None).await? {
+			return Ok(());
+		}
+
+		let _permit = queue::wait_for_interaction(&ctx.http, &cmd.token, invoker.user.id).await?;
+
+		let request = CompileRequest::new(self.0, Playground::new(&block.code, &options));
+		compile(&ctx.http, &ctx.backend, &cmd.token, &request, None).await
+	}
+}
+
+/// Compiles the code and sends what it compiled to as an attachment, or the
+/// compiler's errors if it didn't compile.
+async fn compile(
+	http: &Client,
+	backend: &Arc<dyn ExecutionBackend>,
+	token: &str,
+	request: &CompileRequest,
+	symbol: Option<&str>,
+) -> Result<(), BotError> {
+	let target = Target::parse(&request.target).map_err(BotError::Validation)?;
+	let result = backend.compile(request).await?;
+
+	if !result.success || result.code.is_empty() {
+		let mut errors = render::escape(&result.stderr);
+		if errors.len() > 1900 {
+			let end = (0..=1900).rev().find(|i| errors.is_char_boundary(*i)).unwrap_or(0);
+			errors.truncate(end);
+		}
+		http.interaction(Id::<ApplicationMarker>::new(config::get().bot_id))
+			.create_followup(token)
+			.content(&format!("Result: false\n**Err:** ```{}```", errors))?
+			.exec()
+			.await?;
+		return Ok(());
+	}
+
+	let (content, output) = match symbol.map(|symbol| (symbol, filter_symbol(target, &result.code, symbol))) {
+		Some((symbol, Some(filtered))) => (format!("Here's the {} for `{}`.", target.label(), symbol), filtered),
+		Some((symbol, None)) => (
+			format!("Couldn't find `{}` in the {}, so here's all of it.", symbol, target.label()),
+			result.code.clone()
+		),
+		None => (format!("Here's the {}.", target.label()), result.code.clone()),
+	};
+
+	http.interaction(Id::<ApplicationMarker>::new(config::get().bot_id))
+		.create_followup(token)
+		.content(&content)?
+		.attachments(&[
+			Attachment {
+				description: None,
+				file: output.into_bytes(),
+				filename: format!("playground.{}", target.extension()),
+			}
+		])?
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+/// Cuts the output down to just the definitions whose names contain `symbol`.
+fn filter_symbol(target: Target, output: &str, symbol: &str) -> Option<String> {
+	let starts_definition = |line: &str| match target {
+		Target::Asm => !line.starts_with(char::is_whitespace) && !line.starts_with('.') && line.trim_end().ends_with(':'),
+		Target::LlvmIr => line.starts_with("define "),
+		Target::Mir | Target::Hir => line.trim_start().starts_with("fn ") || line.trim_start().starts_with("pub fn "),
+		Target::Wasm => line.trim_start().starts_with(".type") && line.contains("@function"),
+	};
+	// LLVM IR, MIR and HIR end definitions with a `}` lined up with where they
+	// started, assembly just runs into the next label.
+	let braced = matches!(target, Target::LlvmIr | Target::Mir | Target::Hir);
+
+	let mut found = Vec::new();
+	let mut current: Option<(&str, Vec<&str>)> = None;
+
+	for line in output.lines() {
+		if starts_definition(line) {
+			if let Some((_, definition)) = current.take() {
+				found.push(definition.join("\n"));
+			}
+			if line.contains(symbol) {
+				let indent = &line[..line.len() - line.trim_start().len()];
+				current = Some((indent, Vec::new()));
+			}
+		}
+
+		if let Some((indent, definition)) = current.as_mut() {
+			definition.push(line);
+			// One line definitions (`fn square(x: u32) -> u32 { x * x }`) end where they start.
+			let one_line = definition.len() == 1 && line.trim_end().ends_with('}');
+			if braced && (one_line || line.strip_prefix(*indent) == Some("}")) {
+				found.push(definition.join("\n"));
+				current = None;
+			}
+		}
+	}
+
+	if let Some((_, definition)) = current {
+		found.push(definition.join("\n"));
+	}
+
+	(!found.is_empty()).then(|| found.join("\n\n"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn asm_runs_to_the_next_label() {
+		let output = "\t.text\n\
+			\t.type\tplayground::square,@function\n\
+			playground::square:\n\
+			\timull\t%edi, %eax\n\
+			\tretq\n\
+			.Lfunc_end0:\n\
+			\t.size\tplayground::square, .Lfunc_end0-playground::square\n\
+			playground::main:\n\
+			\tretq\n";
+		assert_eq!(
+			filter_symbol(Target::Asm, output, "square").unwrap(),
+			"playground::square:\n\timull\t%edi, %eax\n\tretq\n.Lfunc_end0:\n\t.size\tplayground::square, .Lfunc_end0-playground::square"
+		);
+		assert_eq!(filter_symbol(Target::Asm, output, "cube"), None);
+	}
+
+	#[test]
+	fn llvm_ir_ends_at_its_brace() {
+		let output = "; playground::square\n\
+			define i32 @_ZN10playground6square(i32 %x) {\n\
+			start:\n  \
+			  %0 = mul i32 %x, %x\n  \
+			  ret i32 %0\n\
+			}\n\
+			\n\
+			define void @_ZN10playground4main() {\n\
+			start:\n  \
+			  ret void\n\
+			}\n";
+		assert_eq!(
+			filter_symbol(Target::LlvmIr, output, "square").unwrap(),
+			"define i32 @_ZN10playground6square(i32 %x) {\nstart:\n  %0 = mul i32 %x, %x\n  ret i32 %0\n}"
+		);
+	}
+
+	#[test]
+	fn mir_skips_braces_that_are_further_in() {
+		let output = "fn square(_1: u32) -> u32 {\n    \
+			let mut _0: u32;\n\n    \
+			bb0: {\n        \
+			    _0 = Mul(copy _1, copy _1);\n        \
+			    return;\n    \
+			}\n\
+			}\n\n\
+			fn main() -> () {\n    \
+			bb0: {\n        \
+			    return;\n    \
+			}\n\
+			}\n";
+		let square = filter_symbol(Target::Mir, output, "square").unwrap();
+		assert!(square.starts_with("fn square"));
+		assert!(square.ends_with("        return;\n    }\n}"));
+		assert!(!square.contains("fn main"));
+	}
+
+	#[test]
+	fn hir_one_liners_and_indented_definitions() {
+		let output = "use std::prelude::rust_2021::*;\n\
+			fn square(x: u32) -> u32 { x * x }\n\
+			mod shapes {\n    \
+			    pub fn square_area(side: u32) -> u32 {\n        \
+			        side * side\n    \
+			    }\n    \
+			    pub fn cube(side: u32) -> u32 { side * side * side }\n\
+			}\n\
+			fn main() { }\n";
+		assert_eq!(
+			filter_symbol(Target::Hir, output, "square").unwrap(),
+			"fn square(x: u32) -> u32 { x * x }\n\n    pub fn square_area(side: u32) -> u32 {\n        side * side\n    }"
+		);
+	}
+
+	#[test]
+	fn wasm_runs_to_the_next_function() {
+		let output = "\t.text\n\
+			\t.globl\t_ZN10playground6square\n\
+			\t.type\t_ZN10playground6square,@function\n\
+			_ZN10playground6square:\n\
+			\t.functype\t_ZN10playground6square (i32) -> (i32)\n\
+			\ti32.mul\n\
+			\tend_function\n\
+			\t.type\tmain,@function\n\
+			main:\n\
+			\tend_function\n";
+		assert_eq!(
+			filter_symbol(Target::Wasm, output, "square").unwrap(),
+			"\t.type\t_ZN10playground6square,@function\n_ZN10playground6square:\n\t.functype\t_ZN10playground6square (i32) -> (i32)\n\ti32.mul\n\tend_function"
+		);
+	}
+}
@@ -1,15 +1,30 @@
 use serde::{Deserialize, Serialize};
-use once_cell::sync::Lazy;
-use std::{fs::read_to_string};
+use once_cell::sync::OnceCell;
+use std::{
+	collections::BTreeMap,
+	env,
+	fmt,
+	fs::{metadata, read_to_string},
+	io,
+	path::{Path, PathBuf},
+	sync::{Arc, RwLock},
+	time::{Duration, SystemTime},
+};
+use tokio::signal::unix::{signal, SignalKind};
+use toml::Value;
+use twilight_model::id::{marker::GuildMarker, Id};
+use tracing::{error, info, warn};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ButtonMenuRole {
 	pub id: u64,
 	pub label: String,
 	pub style: u8
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ButtonMenu {
 	pub channel_id: u64,
 	pub roles: Vec<ButtonMenuRole>,
@@ -17,26 +32,701 @@ pub struct ButtonMenu {
 	pub comp_type: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct ButtonMenuSettings {
 	pub send_on_start: bool,
 	pub messages_to_check: u64
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Default for ButtonMenuSettings {
+	fn default() -> Self {
+		ButtonMenuSettings { send_on_start: false, messages_to_check: 50 }
+	}
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub token: String,
 	pub bot_id: u64,
-	pub server_id: u64,
+	/// The guilds the bot works in, by id.
+	#[serde(with = "guild_ids")]
+	pub guilds: BTreeMap<u64, GuildConfig>,
+	/// Whether the commands are registered in each of `guilds` or globally.
+	#[serde(default)]
+	pub commands: CommandScope,
+	#[serde(default)]
+	pub settings: ButtonMenuSettings,
+	#[serde(default)]
+	pub backend: BackendConfig,
+	/// What happens to a thread of results when the message it's on is deleted.
+	#[serde(default)]
+	pub on_delete: OnDelete,
+	/// Where the bot keeps what it needs to remember between restarts.
+	#[serde(default = "default_data_dir")]
+	pub data_dir: String,
+	#[serde(default)]
+	pub rate_limits: RateLimits,
+	/// How many runs can happen at once, the rest wait in line.
+	#[serde(default = "default_max_concurrent_runs")]
+	pub max_concurrent_runs: usize,
+}
+
+/// What the bot does in one guild.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GuildConfig {
+	/// Channels where code in messages gets run.
+	#[serde(default)]
+	pub channels: Vec<u64>,
+	/// Roles that aren't allowed to use the bot.
+	#[serde(default)]
+	pub banned_roles: Vec<u64>,
 	pub projects_channel: Option<u64>,
 	pub projects_ping_role: Option<u64>,
-    pub channels: Vec<u64>,
-    pub banned_roles: Vec<u64>,
-	pub settings: ButtonMenuSettings,
-	pub button_menus: Vec<ButtonMenu>
+	#[serde(default)]
+	pub button_menus: Vec<ButtonMenu>,
+	/// Where role changes, refusals, projects and `/admin` changes get posted.
+	pub audit_channel: Option<u64>,
+	/// What code gets looked for before it's run, see `screen`.
+	#[serde(default = "default_screening")]
+	pub screening: Vec<ScreenRule>,
+}
+
+/// Code matching `pattern` (a regex) gets `action` taken on it before it's run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScreenRule {
+	/// What it's looking for, shown to whoever ran the code, like "uses the network".
+	pub name: String,
+	pub pattern: String,
+	pub action: ScreenAction,
+}
+
+/// From least to most drastic, when several rules match the most drastic wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScreenAction {
+	/// Run it, but say it was noticed.
+	Warn,
+	/// Only run it once whoever ran it presses "Run anyway".
+	Confirm,
+	Refuse,
+}
+
+fn default_screening() -> Vec<ScreenRule> {
+	let rule = |name: &str, pattern: &str, action| ScreenRule { name: name.into(), pattern: pattern.into(), action };
+	vec![
+		rule("runs other programs", r"process\s*::\s*Command|\bCommand\s*::\s*new", ScreenAction::Confirm),
+		rule("uses the network", r"\bstd\s*::\s*net\b|\b(TcpStream|TcpListener|UdpSocket)\b", ScreenAction::Confirm),
+		rule("includes files", r"\binclude_(bytes|str)\s*!", ScreenAction::Confirm),
+		rule("spawns threads in a loop", r"\b(loop|while)\b[^{]*\{[^{}]*\bspawn\s*\(", ScreenAction::Refuse),
+		rule("prints in a loop that never ends", r"\bloop\s*\{[^{}]*\b(e?print(ln)?|write(ln)?)\s*!", ScreenAction::Warn),
+		rule("spins in an empty loop", r"\bloop\s*\{\s*\}", ScreenAction::Warn),
+	]
+}
+
+/// TOML keys are always strings, so the guild ids in `guilds` are parsed out of them.
+mod guild_ids {
+	use std::collections::BTreeMap;
+
+	use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+	use super::GuildConfig;
+
+	pub fn serialize<S: Serializer>(guilds: &BTreeMap<u64, GuildConfig>, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_map(guilds.iter().map(|(id, guild)| (id.to_string(), guild)))
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<u64, GuildConfig>, D::Error> {
+		BTreeMap::<String, GuildConfig>::deserialize(deserializer)?
+			.into_iter()
+			.map(|(id, guild)| match id.parse() {
+				Ok(parsed) => Ok((parsed, guild)),
+				Err(_) => Err(D::Error::custom(format!("`{}` in `guilds` isn't a guild id", id))),
+			})
+			.collect()
+	}
+}
+
+/// Deserializing where a string field takes a number or `true` as its text.
+/// Environment variables don't say what type they are, so one that looks like
+/// a number can still be a path or a token.
+mod lenient {
+	use serde::de::{value::{MapDeserializer, SeqDeserializer}, Deserializer, IntoDeserializer, Visitor};
+	use toml::{de::Error, Value};
+
+	pub struct Lenient(pub Value);
+
+	impl<'de> IntoDeserializer<'de, Error> for Lenient {
+		type Deserializer = Self;
+
+		fn into_deserializer(self) -> Self {
+			self
+		}
+	}
+
+	impl<'de> Deserializer<'de> for Lenient {
+		type Error = Error;
+
+		fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+			match self.0 {
+				Value::Table(table) => visitor.visit_map(MapDeserializer::new(table.into_iter().map(|(key, value)| (key, Lenient(value))))),
+				Value::Array(array) => visitor.visit_seq(SeqDeserializer::new(array.into_iter().map(Lenient))),
+				value => value.deserialize_any(visitor),
+			}
+		}
+
+		fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+			match self.0 {
+				value @ (Value::Integer(_) | Value::Float(_) | Value::Boolean(_) | Value::Datetime(_)) => visitor.visit_string(value.to_string()),
+				value => Lenient(value).deserialize_any(visitor),
+			}
+		}
+
+		fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+			self.deserialize_str(visitor)
+		}
+
+		fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+			visitor.visit_some(self)
+		}
+
+		fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+			visitor.visit_newtype_struct(self)
+		}
+
+		fn deserialize_enum<V: Visitor<'de>>(
+			self,
+			name: &'static str,
+			variants: &'static [&'static str],
+			visitor: V,
+		) -> Result<V::Value, Error> {
+			self.0.deserialize_enum(name, variants, visitor)
+		}
+
+		serde::forward_to_deserialize_any! {
+			bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char bytes byte_buf
+			unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommandScope {
+	/// Registered in each guild, they show up straight away.
+	#[default]
+	Guild,
+	/// Registered once for everywhere, Discord can take a while to show changes.
+	Global,
+}
+
+fn default_max_concurrent_runs() -> usize {
+	4
+}
+
+/// How much code can be run, by one user, in one channel and in the whole guild.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimits {
+	#[serde(default = "default_user_limit")]
+	pub user: BucketConfig,
+	#[serde(default = "default_channel_limit")]
+	pub channel: BucketConfig,
+	#[serde(default = "default_guild_limit")]
+	pub guild: BucketConfig,
+}
+
+impl Default for RateLimits {
+	fn default() -> Self {
+		RateLimits {
+			user: default_user_limit(),
+			channel: default_channel_limit(),
+			guild: default_guild_limit(),
+		}
+	}
+}
+
+/// A token bucket: `burst` runs can happen at once, and it refills at
+/// `per_minute` runs a minute. A `per_minute` of 0 turns the limit off.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BucketConfig {
+	pub burst: u32,
+	pub per_minute: u32,
+}
+
+fn default_user_limit() -> BucketConfig { BucketConfig { burst: 5, per_minute: 6 } }
+fn default_channel_limit() -> BucketConfig { BucketConfig { burst: 15, per_minute: 30 } }
+fn default_guild_limit() -> BucketConfig { BucketConfig { burst: 30, per_minute: 60 } }
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnDelete {
+	Delete,
+	/// Archive and lock the thread, so the results can still be looked at.
+	#[default]
+	Archive,
+}
+
+fn default_data_dir() -> String {
+	String::from("data")
+}
+
+/// `kind` picks which, and defaults to remote so `RMR_BACKEND__URL` works without a `[backend]`.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum BackendConfig {
+	Remote(RemoteBackendConfig),
+	Local(LocalBackendConfig),
+}
+
+// Done by hand rather than with `#[serde(tag)]`, which would guess the fields'
+// types before they get to `Lenient` and lose the string fallback.
+impl<'de> Deserialize<'de> for BackendConfig {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		use serde::de::Error;
+
+		let mut table = toml::value::Table::deserialize(deserializer)?;
+		let kind = match table.remove("kind") {
+			Some(Value::String(kind)) => kind,
+			Some(other) => return Err(D::Error::custom(format!("backend kind has to be a string, not {}", other))),
+			None => String::from("remote"),
+		};
+		let fields = lenient::Lenient(Value::Table(table));
+		match kind.as_str() {
+			"remote" => RemoteBackendConfig::deserialize(fields).map(BackendConfig::Remote),
+			"local" => LocalBackendConfig::deserialize(fields).map(BackendConfig::Local),
+			_ => return Err(D::Error::unknown_variant(&kind, &["remote", "local"])),
+		}.map_err(D::Error::custom)
+	}
+}
+
+impl Default for BackendConfig {
+	fn default() -> Self {
+		BackendConfig::Remote(RemoteBackendConfig {
+			url: default_playground_url(),
+			timeout_secs: default_playground_timeout_secs(),
+			retries: default_retries(),
+			failure_threshold: default_failure_threshold(),
+			cooldown_secs: default_cooldown_secs(),
+		})
+	}
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteBackendConfig {
+	#[serde(default = "default_playground_url")]
+	pub url: String,
+	/// How long a request gets before it's given up on.
+	#[serde(default = "default_playground_timeout_secs")]
+	pub timeout_secs: u64,
+	/// How many times to try again when the playground fails a request.
+	#[serde(default = "default_retries")]
+	pub retries: u32,
+	/// How many failed requests in a row before the playground is taken to be
+	/// down, and requests fail straight away for `cooldown_secs`.
+	#[serde(default = "default_failure_threshold")]
+	pub failure_threshold: u32,
+	#[serde(default = "default_cooldown_secs")]
+	pub cooldown_secs: u64,
+}
+
+fn default_playground_url() -> String {
+	String::from("https://play.rust-lang.org")
+}
+fn default_playground_timeout_secs() -> u64 { 30 }
+fn default_retries() -> u32 { 2 }
+fn default_failure_threshold() -> u32 { 5 }
+fn default_cooldown_secs() -> u64 { 30 }
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LocalBackendConfig {
+	/// Where the throwaway directories get made. Defaults to the system temp dir.
+	pub work_dir: Option<String>,
+	#[serde(default = "default_timeout_secs")]
+	pub timeout_secs: u64,
+	#[serde(default = "default_cpu_secs")]
+	pub cpu_secs: u64,
+	#[serde(default = "default_memory_mb")]
+	pub memory_mb: u64,
+	#[serde(default = "default_max_output_bytes")]
+	pub max_output_bytes: usize,
+	/// Caps the processes (and threads) run code can start, so fork bombs don't
+	/// get far. It counts everything the bot's user runs, the bot included.
+	#[serde(default = "default_max_processes")]
+	pub max_processes: u64,
+	/// The user and group ids the compilers and run code are run as, in a
+	/// network namespace of their own. Switching needs the bot to run as root.
+	/// Without them everything runs as the bot, which can read its token.
+	pub run_as_uid: Option<u32>,
+	pub run_as_gid: Option<u32>,
+	/// Where to serve pastes of run code from, like `0.0.0.0:8080`. Without it
+	/// replies don't get a link to the code.
+	pub paste_bind: Option<String>,
+	/// The url people can reach the paste server at.
+	pub paste_url: Option<String>,
+	#[serde(default = "default_paste_dir")]
+	pub paste_dir: String,
+}
+
+fn default_timeout_secs() -> u64 { 15 }
+fn default_cpu_secs() -> u64 { 10 }
+fn default_memory_mb() -> u64 { 512 }
+fn default_max_output_bytes() -> usize { 64 * 1024 }
+fn default_max_processes() -> u64 { 256 }
+fn default_paste_dir() -> String { String::from("pastes") }
+
+/// Environment variables starting with this override fields from the config
+/// file, like `RMR_TOKEN` for `token`. Nested fields have `__` between them,
+/// like `RMR_BACKEND__URL`.
+const ENV_PREFIX: &str = "RMR_";
+
+/// Fields that are left out when the config is shown.
+const SECRETS: &[&str] = &["token"];
+
+/// How often the config file is checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+static CONFIG: OnceCell<RwLock<Arc<Config>>> = OnceCell::new();
+
+/// The config as it is right now. Something that should see the same config
+/// all the way through can hang on to it, a reload won't change it underneath.
+pub fn get() -> Arc<Config> {
+	CONFIG.get().expect("the config hasn't been loaded").read().unwrap().clone()
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+	Read(PathBuf, io::Error),
+	/// It isn't TOML, or has fields that are the wrong type or don't exist.
+	Parse(PathBuf, toml::de::Error),
+	/// It parsed, but some of what's in it doesn't make sense.
+	Invalid(PathBuf, Vec<String>),
+	/// An `RMR_` variable couldn't be put in the config, holds its name and why.
+	Env(String, String),
+}
+
+impl fmt::Display for ConfigError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ConfigError::Read(path, why) => write!(f, "couldn't read {}: {}", path.display(), why),
+			ConfigError::Parse(path, why) => write!(f, "{} isn't valid: {}", path.display(), why),
+			ConfigError::Invalid(path, problems) => {
+				write!(f, "{} has problems:", path.display())?;
+				for problem in problems {
+					write!(f, "\n  - {}", problem)?;
+				}
+				Ok(())
+			}
+			ConfigError::Env(name, why) => write!(f, "couldn't use {}: {}", name, why),
+		}
+	}
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+	/// What the bot does in `guild`, if it's set up there.
+	pub fn guild(&self, guild: Id<GuildMarker>) -> Option<&GuildConfig> {
+		self.guilds.get(&guild.get())
+	}
+
+	/// Everything wrong with the config that parsing it doesn't catch.
+	pub fn problems(&self) -> Vec<String> {
+		let mut problems = Vec::new();
+		if self.token.is_empty() {
+			problems.push(String::from("token is empty"));
+		}
+		if self.max_concurrent_runs == 0 {
+			problems.push(String::from("max_concurrent_runs has to be at least 1, or nothing would ever run"));
+		}
+		if !(1..=100).contains(&self.settings.messages_to_check) {
+			problems.push(format!(
+				"settings.messages_to_check is {}, Discord only gives 1 to 100 messages at a time",
+				self.settings.messages_to_check
+			));
+		}
+
+		for (name, limit) in [("user", &self.rate_limits.user), ("channel", &self.rate_limits.channel), ("guild", &self.rate_limits.guild)] {
+			if limit.burst == 0 && limit.per_minute > 0 {
+				problems.push(format!(
+					"rate_limits.{}.burst is 0, so nothing could ever be run (per_minute = 0 turns the limit off)",
+					name
+				));
+			}
+		}
+
+		if let BackendConfig::Local(local) = &self.backend {
+			if local.run_as_uid.is_some() != local.run_as_gid.is_some() {
+				problems.push(String::from("backend.run_as_uid and backend.run_as_gid have to be set together"));
+			}
+			match &local.paste_bind {
+				Some(bind) => if let Err(why) = bind.parse::<std::net::SocketAddr>() {
+					problems.push(format!("backend.paste_bind is {:?}, which isn't an address like 0.0.0.0:8080: {}", bind, why));
+				},
+				None => if local.paste_url.is_some() {
+					problems.push(String::from("backend.paste_url is set without backend.paste_bind, so nothing serves the pastes"));
+				},
+			}
+		}
+
+		if self.guilds.is_empty() {
+			problems.push(String::from("guilds is empty, the bot has to be set up in at least one"));
+		}
+
+		let mut ids = vec![(String::from("bot_id"), self.bot_id)];
+		for (&id, guild) in &self.guilds {
+			let field = |name: &str| format!("guilds.{}.{}", id, name);
+			ids.push((String::from("guilds"), id));
+			ids.extend(guild.projects_channel.map(|id| (field("projects_channel"), id)));
+			ids.extend(guild.projects_ping_role.map(|id| (field("projects_ping_role"), id)));
+			ids.extend(guild.audit_channel.map(|id| (field("audit_channel"), id)));
+			ids.extend(guild.channels.iter().map(|&id| (field("channels"), id)));
+			ids.extend(guild.banned_roles.iter().map(|&id| (field("banned_roles"), id)));
+
+			for (i, rule) in guild.screening.iter().enumerate() {
+				if let Err(why) = regex::Regex::new(&rule.pattern) {
+					problems.push(format!("{}'s pattern isn't a valid regex: {}", field(&format!("screening[{}]", i)), why));
+				}
+			}
+
+			for (i, menu) in guild.button_menus.iter().enumerate() {
+				let menu_field = field(&format!("button_menus[{}]", i));
+				ids.push((format!("{}.channel_id", menu_field), menu.channel_id));
+				if menu.roles.len() > 5 {
+					problems.push(format!("{} has {} roles, a row only fits 5 buttons", menu_field, menu.roles.len()));
+				}
+				for (j, role) in menu.roles.iter().enumerate() {
+					ids.push((format!("{}.roles[{}].id", menu_field, j), role.id));
+					if !(1..=4).contains(&role.style) {
+						problems.push(format!(
+							"{}.roles[{}].style is {}, it has to be 1 (blurple), 2 (grey), 3 (green) or 4 (red)",
+							menu_field, j, role.style
+						));
+					}
+				}
+			}
+		}
+
+		// Discord ids start with a timestamp, so anything this small is a typo.
+		problems.extend(ids.into_iter()
+			.filter(|(_, id)| *id < 1 << 22)
+			.map(|(field, id)| format!("{} has {}, which isn't a Discord id", field, id)));
+
+		problems
+	}
+
+	/// The fields that only get read when the bot starts, so changing them
+	/// needs a restart.
+	fn needs_restart(&self, new: &Config) -> Vec<&'static str> {
+		let mut fields = Vec::new();
+		if self.token != new.token { fields.push("`token`") }
+		if self.bot_id != new.bot_id { fields.push("`bot_id`") }
+		if self.commands != new.commands { fields.push("`commands`") }
+		// Commands get registered in each guild at startup.
+		if self.commands == CommandScope::Guild && !self.guilds.keys().eq(new.guilds.keys()) {
+			fields.push("Which guilds are in `guilds`")
+		}
+		if self.backend != new.backend { fields.push("`backend`") }
+		if self.data_dir != new.data_dir { fields.push("`data_dir`") }
+		fields
+	}
+
+	/// The config as TOML, with the secrets blanked out so it can be shown.
+	pub fn redacted(&self) -> String {
+		let mut value = Value::try_from(self).expect("the config is always valid TOML");
+		if let Some(table) = value.as_table_mut() {
+			for secret in SECRETS {
+				if let Some(field) = table.get_mut(*secret) {
+					*field = Value::String(String::from("<redacted>"));
+				}
+			}
+		}
+		value.to_string()
+	}
 }
 
-pub static CONFIG: Lazy<Config> = Lazy::new(|| {
-    let contents = read_to_string("config.toml").expect("Could not open config.toml");
-    toml::from_str(&contents).unwrap()
-});
+/// Reads and checks the config at `path`, with any `RMR_` variables put over it.
+pub fn load(path: &Path) -> Result<Config, ConfigError> {
+	let contents = read_to_string(path).map_err(|why| ConfigError::Read(path.to_path_buf(), why))?;
+	let mut value: Value = toml::from_str(&contents).map_err(|why| ConfigError::Parse(path.to_path_buf(), why))?;
+	apply_env(&mut value, env::vars())?;
+	let config = Config::deserialize(lenient::Lenient(value)).map_err(|why| ConfigError::Parse(path.to_path_buf(), why))?;
+
+	let problems = config.problems();
+	if !problems.is_empty() {
+		return Err(ConfigError::Invalid(path.to_path_buf(), problems));
+	}
+	Ok(config)
+}
+
+/// Puts the `RMR_` variables in `vars` over the fields they're for.
+fn apply_env(config: &mut Value, vars: impl Iterator<Item = (String, String)>) -> Result<(), ConfigError> {
+	for (name, raw) in vars {
+		let path = match name.strip_prefix(ENV_PREFIX) {
+			Some(path) => path.to_lowercase(),
+			None => continue,
+		};
+		let keys: Vec<&str> = path.split("__").collect();
+		let (field, parents) = keys.split_last().expect("split always gives something");
+
+		let mut table = config.as_table_mut().expect("the config is always a table");
+		for key in parents {
+			table = table.entry(key.to_string())
+				.or_insert_with(|| Value::Table(Default::default()))
+				.as_table_mut()
+				.ok_or_else(|| ConfigError::Env(name.clone(), format!("`{}` doesn't have fields", key)))?;
+		}
+		let value = env_value(&raw, table.get(*field));
+		table.insert(field.to_string(), value);
+	}
+	Ok(())
+}
+
+/// What's in a variable, as whatever TOML it is (numbers, `true`, lists like
+/// `[1, 2]`), or as a string if it isn't any. Replacing a string it's always a
+/// string, a token or a path can look like a number too.
+fn env_value(raw: &str, replacing: Option<&Value>) -> Value {
+	if let Some(Value::String(_)) = replacing {
+		return Value::String(raw.to_string());
+	}
+
+	toml::from_str::<toml::value::Table>(&format!("value = {}", raw))
+		.ok()
+		.and_then(|mut table| table.remove("value"))
+		.unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+/// Loads the config for the first time, before anything uses `get`.
+pub fn init(path: &Path) -> Result<(), ConfigError> {
+	let config = load(path)?;
+	CONFIG.set(RwLock::new(Arc::new(config))).expect("the config was already loaded");
+	Ok(())
+}
+
+/// Loads the config again. If the new one has problems they're logged and the
+/// old one's kept.
+pub fn reload(path: &Path) {
+	let config = match load(path) {
+		Ok(config) => config,
+		Err(why) => {
+			error!("Not reloading the config, {}", why);
+			return;
+		}
+	};
+
+	let mut current = CONFIG.get().expect("the config hasn't been loaded").write().unwrap();
+	if **current == config {
+		return;
+	}
+	for field in current.needs_restart(&config) {
+		warn!("{} changed in the config, that only takes effect after a restart", field);
+	}
+	*current = Arc::new(config);
+	info!("Reloaded the config");
+}
+
+/// Reloads the config whenever the file at `path` changes, or the bot gets a SIGHUP.
+pub async fn watch(path: PathBuf) {
+	let mut hangups = signal(SignalKind::hangup()).expect("couldn't listen for SIGHUP");
+	let mut interval = tokio::time::interval(WATCH_INTERVAL);
+	let modified = |path: &Path| metadata(path).and_then(|m| m.modified()).ok();
+	let mut last_modified: Option<SystemTime> = modified(&path);
+
+	loop {
+		tokio::select! {
+			_ = interval.tick() => {
+				let now = modified(&path);
+				if now == last_modified {
+					continue;
+				}
+				last_modified = now;
+			}
+			_ = hangups.recv() => info!("Got a SIGHUP, reloading the config"),
+		}
+		reload(&path);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(contents: &str) -> Config {
+		let config: Config = toml::from_str(contents).unwrap();
+		assert_eq!(config.problems(), Vec::<String>::new());
+		config
+	}
+
+	#[test]
+	fn example_config_is_valid() {
+		parse(include_str!("../config.example.toml"));
+	}
+
+	#[test]
+	fn readme_config_is_valid() {
+		let readme = include_str!("../README.md");
+		let start = readme.find("```toml\n# config.toml\n").unwrap() + "```toml\n".len();
+		let end = start + readme[start..].find("```").unwrap();
+		let config = parse(&readme[start..end]);
+		assert_eq!(config.guilds[&876543210987654321].audit_channel, Some(543210987654321098));
+	}
+
+	#[test]
+	fn paste_settings_are_checked() {
+		let mut config: Config = toml::from_str(include_str!("../config.example.toml")).unwrap();
+		let local = |bind: Option<&str>, url: Option<&str>| {
+			let mut local: LocalBackendConfig = toml::from_str("").unwrap();
+			local.paste_bind = bind.map(String::from);
+			local.paste_url = url.map(String::from);
+			BackendConfig::Local(local)
+		};
+
+		config.backend = local(Some("0.0.0.0:8080"), Some("https://pastes.example.com"));
+		assert!(config.problems().is_empty());
+		config.backend = local(Some("localhost"), None);
+		assert_eq!(config.problems().len(), 1);
+		config.backend = local(None, Some("https://pastes.example.com"));
+		assert_eq!(config.problems().len(), 1);
+	}
+
+	#[test]
+	fn env_values_are_toml() {
+		assert_eq!(env_value("5", None), Value::Integer(5));
+		assert_eq!(env_value("[1, 2]", None), Value::Array(vec![Value::Integer(1), Value::Integer(2)]));
+		assert_eq!(env_value("https://play.rust-lang.org", None), Value::String(String::from("https://play.rust-lang.org")));
+	}
+
+	fn with_env(contents: &str, vars: &[(&str, &str)]) -> Config {
+		let mut config: Value = toml::from_str(contents).unwrap();
+		apply_env(&mut config, vars.iter().map(|(name, value)| (name.to_string(), value.to_string()))).unwrap();
+		Config::deserialize(lenient::Lenient(config)).unwrap()
+	}
+
+	#[test]
+	fn env_values_replacing_strings_stay_strings() {
+		let vars = [("RMR_TOKEN", "12345"), ("RMR_DATA_DIR", "2022"), ("RMR_RATE_LIMITS__USER__BURST", "2")];
+		let config = with_env(include_str!("../config.example.toml"), &vars);
+		assert_eq!(config.token, "12345");
+		assert_eq!(config.data_dir, "2022");
+		assert_eq!(config.rate_limits.user.burst, 2);
+	}
+
+	#[test]
+	fn env_values_for_missing_fields_get_their_type() {
+		let file = "token = \"abc\"\nbot_id = 1\n[guilds.1]";
+		let config = with_env(file, &[("RMR_DATA_DIR", "2022"), ("RMR_BACKEND__URL", "http://localhost:8000")]);
+		assert_eq!(config.data_dir, "2022");
+		assert!(matches!(config.backend, BackendConfig::Remote(ref remote) if remote.url == "http://localhost:8000"));
+
+		let config = with_env(file, &[("RMR_BACKEND__KIND", "local"), ("RMR_BACKEND__WORK_DIR", "2022"), ("RMR_BACKEND__CPU_SECS", "3")]);
+		let BackendConfig::Local(local) = config.backend else { panic!("the backend isn't local") };
+		assert_eq!(local.work_dir.as_deref(), Some("2022"));
+		assert_eq!(local.cpu_secs, 3);
+	}
+}
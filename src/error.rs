@@ -0,0 +1,256 @@
+use std::{fmt, time::Duration};
+
+use tracing::{error, info};
+use twilight_gateway::Event;
+use twilight_http::{response::DeserializeBodyError, Client};
+use twilight_model::{
+	application::interaction::Interaction,
+	channel::message::MessageFlags,
+	http::interaction::{InteractionResponse, InteractionResponseData, InteractionResponseType},
+	id::{marker::{ApplicationMarker, ChannelMarker, GuildMarker, InteractionMarker, MessageMarker, UserMarker}, Id},
+};
+use twilight_validate::{
+	channel::ChannelValidationError,
+	embed::EmbedValidationError,
+	message::MessageValidationError,
+	request::ValidationError,
+};
+
+use crate::audit;
+use crate::backend::BackendError;
+use crate::config;
+use crate::limits;
+use crate::playground::PlaygroundError;
+use crate::storage::UserBan;
+
+/// Everything that can go wrong handling an event. Each one knows what to tell
+/// whoever caused it, see `user_message`.
+#[derive(Debug)]
+pub enum BotError {
+	/// Talking to Discord failed, or what would've been sent to it wasn't valid.
+	Discord(Box<dyn std::error::Error + Send + Sync>),
+	/// Running code failed before there was a result to show.
+	Playground(BackendError),
+	Storage(rusqlite::Error),
+	/// The bot isn't set up for what was asked of it.
+	Config(String),
+	/// Whoever asked isn't allowed to. Holds what to tell them.
+	Permission(String),
+	/// What was asked for doesn't make sense, like options that don't exist.
+	/// Holds what to tell them.
+	Validation(String),
+	/// Too much has been run lately, more can be run after this long.
+	RateLimited(Duration),
+	/// Whoever asked has been banned from using the bot with `/admin ban-user`.
+	Blocked(UserBan),
+}
+
+impl BotError {
+	/// What to tell the person who ran into this.
+	pub fn user_message(&self) -> String {
+		match self {
+			BotError::Discord(_) => String::from("❌ Something went wrong talking to Discord, try again in a bit."),
+			BotError::Playground(why) => match why.downcast_ref::<PlaygroundError>() {
+				Some(why) => why.to_string(),
+				None => String::from("❌ Something went wrong running that, try again in a bit."),
+			},
+			BotError::Storage(_) => String::from("❌ Couldn't get to the bot's database, try again in a bit."),
+			BotError::Config(what) => format!("❌ The bot isn't set up for that ({}), let a mod know.", what),
+			BotError::Permission(message) | BotError::Validation(message) => message.clone(),
+			BotError::RateLimited(wait) => limits::cooldown(*wait),
+			BotError::Blocked(ban) => {
+				let mut message = String::from("🚫 You're blocked from using the bot");
+				if let Some(until) = ban.until {
+					message.push_str(&format!(" until <t:{}:f>", until));
+				}
+				if let Some(reason) = &ban.reason {
+					message.push_str(&format!(": {}", reason));
+				}
+				message
+			}
+		}
+	}
+
+	/// Whether it's down to whoever asked rather than something being broken.
+	fn is_expected(&self) -> bool {
+		matches!(self, BotError::Permission(_) | BotError::Validation(_) | BotError::RateLimited(_) | BotError::Blocked(_))
+	}
+}
+
+impl fmt::Display for BotError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			BotError::Discord(why) => write!(f, "discord: {}", why),
+			BotError::Playground(why) => write!(f, "playground: {}", why),
+			BotError::Storage(why) => write!(f, "storage: {}", why),
+			BotError::Config(why) => write!(f, "config: {}", why),
+			BotError::Permission(why) => write!(f, "not allowed: {}", why),
+			BotError::Validation(why) => write!(f, "invalid: {}", why),
+			BotError::RateLimited(wait) => write!(f, "rate limited for {:?}", wait),
+			BotError::Blocked(ban) => write!(f, "{} is blocked in {} (until {:?}, by {})", ban.user, ban.guild, ban.until, ban.moderator),
+		}
+	}
+}
+
+impl std::error::Error for BotError {}
+
+macro_rules! discord_errors {
+	($($error:ty),*) => {
+		$(
+			impl From<$error> for BotError {
+				fn from(why: $error) -> Self {
+					BotError::Discord(Box::new(why))
+				}
+			}
+		)*
+	};
+}
+
+discord_errors!(
+	twilight_http::Error,
+	DeserializeBodyError,
+	MessageValidationError,
+	ChannelValidationError,
+	EmbedValidationError,
+	ValidationError
+);
+
+impl From<BackendError> for BotError {
+	fn from(why: BackendError) -> Self {
+		BotError::Playground(why)
+	}
+}
+
+impl From<rusqlite::Error> for BotError {
+	fn from(why: rusqlite::Error) -> Self {
+		BotError::Storage(why)
+	}
+}
+
+/// How long a reply about a message being refused stays up. Messages can't get
+/// ephemeral answers, so this is the next best thing.
+const REFUSAL_LIFETIME: Duration = Duration::from_secs(30);
+
+/// Logs an error from handling an event, and tells whoever caused it what
+/// happened. Messages get a reply (taken down after a bit if it's down to
+/// them), interactions an ephemeral message (as the response if there hasn't
+/// been one yet, otherwise a followup).
+pub async fn report(http: &Client, event: &Event, why: BotError) {
+	if why.is_expected() {
+		info!("Refused to handle an event: {}", why);
+	} else {
+		error!("Error handling event: {}", why);
+	}
+
+	if let (BotError::Permission(_) | BotError::Blocked(_), Some((guild, user, action))) = (&why, attempt(event)) {
+		let reason = match &why {
+			BotError::Blocked(ban) => {
+				let mut reason = format!("Banned by <@{}>", ban.moderator);
+				if let Some(until) = ban.until {
+					reason.push_str(&format!(" until <t:{}:f>", until));
+				}
+				if let Some(why) = &ban.reason {
+					reason.push_str(&format!(": {}", why));
+				}
+				reason
+			}
+			_ => why.user_message(),
+		};
+		audit::log(http, guild, audit::Entry::Refused { user, action, reason }).await;
+	}
+
+	let content = why.user_message();
+	let sent = match event {
+		Event::MessageCreate(message) => send_reply(http, message.channel_id, message.id, &content, why.is_expected()).await,
+		Event::MessageUpdate(update) => send_reply(http, update.channel_id, update.id, &content, why.is_expected()).await,
+		Event::InteractionCreate(interaction) => {
+			let (id, token) = match &interaction.0 {
+				Interaction::ApplicationCommand(cmd) => (cmd.id, &cmd.token),
+				Interaction::MessageComponent(msgcmp) => (msgcmp.id, &msgcmp.token),
+				Interaction::ModalSubmit(modal) => (modal.id, &modal.token),
+				_ => return,
+			};
+			send_ephemeral(http, id, token, &content).await
+		}
+		_ => Ok(()),
+	};
+
+	if let Err(why) = sent {
+		error!("Failed to tell someone about an error: {}", why);
+	}
+}
+
+/// Who tried what, and where, for the audit log.
+fn attempt(event: &Event) -> Option<(Id<GuildMarker>, Id<UserMarker>, String)> {
+	match event {
+		Event::MessageCreate(message) => Some((
+			message.guild_id?,
+			message.author.id,
+			format!("Running code in <#{}>", message.channel_id),
+		)),
+		Event::MessageUpdate(update) => Some((
+			update.guild_id?,
+			update.author.as_ref()?.id,
+			format!("Running an edited message in <#{}>", update.channel_id),
+		)),
+		Event::InteractionCreate(interaction) => {
+			let (member, action) = match &interaction.0 {
+				Interaction::ApplicationCommand(cmd) => (&cmd.member, format!("`/{}`", cmd.data.name)),
+				Interaction::MessageComponent(msgcmp) => (&msgcmp.member, format!("Pressing a button in <#{}>", msgcmp.channel_id)),
+				Interaction::ModalSubmit(modal) => (&modal.member, format!("Sending a form in <#{}>", modal.channel_id)),
+				_ => return None,
+			};
+			Some((interaction.guild_id()?, member.as_ref()?.user.as_ref()?.id, action))
+		}
+		_ => None,
+	}
+}
+
+async fn send_reply(
+	http: &Client,
+	channel: Id<ChannelMarker>,
+	message: Id<MessageMarker>,
+	content: &str,
+	temporary: bool,
+) -> Result<(), BotError> {
+	let reply = http.create_message(channel)
+		.reply(message)
+		.content(content)?
+		.exec()
+		.await?
+		.model()
+		.await?;
+
+	if temporary {
+		tokio::time::sleep(REFUSAL_LIFETIME).await;
+		http.delete_message(channel, reply.id).exec().await?;
+	}
+	Ok(())
+}
+
+async fn send_ephemeral(
+	http: &Client,
+	id: Id<InteractionMarker>,
+	token: &str,
+	content: &str,
+) -> Result<(), BotError> {
+	let interaction = http.interaction(Id::<ApplicationMarker>::new(config::get().bot_id));
+	let response = InteractionResponse {
+		kind: InteractionResponseType::ChannelMessageWithSource,
+		data: Some(InteractionResponseData {
+			content: Some(content.to_string()),
+			flags: Some(MessageFlags::EPHEMERAL),
+			..Default::default()
+		}),
+	};
+
+	// Most interactions have been responded to (or deferred) by the time anything goes wrong.
+	if interaction.create_response(id, token, &response).exec().await.is_err() {
+		interaction.create_followup(token)
+			.content(content)?
+			.flags(MessageFlags::EPHEMERAL)
+			.exec()
+			.await?;
+	}
+	Ok(())
+}
@@ -0,0 +1,259 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use twilight_cache_inmemory::InMemoryCache;
+use twilight_http::{client::InteractionClient, Client};
+use twilight_model::{
+	application::{
+		command::{Command, CommandType},
+		interaction::{modal::ModalSubmitInteraction, ApplicationCommand, Interaction, MessageComponentInteraction},
+	},
+	channel::{message::MessageFlags, Message},
+	guild::PartialMember,
+	http::interaction::{InteractionResponse, InteractionResponseData, InteractionResponseType},
+	id::{marker::{ApplicationMarker, GuildMarker, InteractionMarker, RoleMarker, UserMarker}, Id},
+	user::User,
+};
+use twilight_util::builder::command::CommandBuilder;
+
+use crate::backend::ExecutionBackend;
+use crate::config;
+use crate::error::BotError;
+use crate::settings;
+use crate::storage::{self, Setting};
+
+/// Interactions only come with the member's roles sometimes, so they have to be in the cache.
+const NOT_CACHED: &str = "You're not cached. Send a message somewhere and press me again.";
+const BANNED: &str = "You're not allowed to use the bot.";
+
+/// What every handler gets to work with.
+pub struct Context {
+	pub http: Arc<Client>,
+	pub cache: Arc<InMemoryCache>,
+	pub backend: Arc<dyn ExecutionBackend>,
+}
+
+impl Context {
+	pub fn interaction(&self) -> InteractionClient<'_> {
+		self.http.interaction(Id::<ApplicationMarker>::new(config::get().bot_id))
+	}
+
+	/// Tells Discord an answer is coming, for anything that takes a while. The
+	/// first followup takes its place, so that can't be anything only for them.
+	pub async fn defer(&self, id: Id<InteractionMarker>, token: &str) -> Result<(), BotError> {
+		self.interaction()
+			.create_response(id, token, &InteractionResponse {
+				kind: InteractionResponseType::DeferredChannelMessageWithSource,
+				data: None,
+			})
+			.exec()
+			.await?;
+		Ok(())
+	}
+
+	/// `defer`, for an answer only whoever used the interaction will see.
+	pub async fn defer_ephemeral(&self, id: Id<InteractionMarker>, token: &str) -> Result<(), BotError> {
+		self.interaction()
+			.create_response(id, token, &InteractionResponse {
+				kind: InteractionResponseType::DeferredChannelMessageWithSource,
+				data: Some(InteractionResponseData {
+					flags: Some(MessageFlags::EPHEMERAL),
+					..Default::default()
+				}),
+			})
+			.exec()
+			.await?;
+		Ok(())
+	}
+
+	/// Answers an interaction straight away, with a message only whoever used it can see.
+	pub async fn respond_ephemeral(&self, id: Id<InteractionMarker>, token: &str, content: &str) -> Result<(), BotError> {
+		self.interaction()
+			.create_response(id, token, &InteractionResponse {
+				kind: InteractionResponseType::ChannelMessageWithSource,
+				data: Some(InteractionResponseData {
+					content: Some(content.to_string()),
+					flags: Some(MessageFlags::EPHEMERAL),
+					..Default::default()
+				}),
+			})
+			.exec()
+			.await?;
+		Ok(())
+	}
+
+	/// Sends a followup only whoever used the interaction can see.
+	pub async fn ephemeral(&self, token: &str, content: &str) -> Result<(), BotError> {
+		self.interaction()
+			.create_followup(token)
+			.content(content)?
+			.flags(MessageFlags::EPHEMERAL)
+			.exec()
+			.await?;
+		Ok(())
+	}
+}
+
+/// Whoever used an interaction, once they've got through `guard`.
+pub struct Invoker {
+	pub user: User,
+	pub guild: Id<GuildMarker>,
+	pub roles: Vec<Id<RoleMarker>>,
+}
+
+#[async_trait]
+pub trait SlashCommand: Send + Sync {
+	/// The command to register, the guild gets filled in by the registry.
+	fn command(&self) -> Command;
+
+	async fn run(&self, ctx: &Context, cmd: &ApplicationCommand, invoker: &Invoker) -> Result<(), BotError>;
+}
+
+/// A command in the menu you get right clicking a message.
+#[async_trait]
+pub trait MessageCommand: Send + Sync {
+	fn name(&self) -> String;
+
+	async fn run(&self, ctx: &Context, cmd: &ApplicationCommand, invoker: &Invoker, message: &Message) -> Result<(), BotError>;
+}
+
+/// Buttons (and anything else with a custom id) on the bot's messages.
+#[async_trait]
+pub trait ComponentHandler: Send + Sync {
+	/// Whether the component with this id is this handler's to deal with.
+	fn handles(&self, custom_id: &str) -> bool;
+
+	async fn run(&self, ctx: &Context, msgcmp: &MessageComponentInteraction, invoker: &Invoker) -> Result<(), BotError>;
+}
+
+#[async_trait]
+pub trait ModalHandler: Send + Sync {
+	/// Whether the modal with this id is this handler's to deal with.
+	fn handles(&self, custom_id: &str) -> bool;
+
+	async fn run(&self, ctx: &Context, modal: &ModalSubmitInteraction, invoker: &Invoker) -> Result<(), BotError>;
+}
+
+/// Every command and interaction the bot handles. Adding one is making a
+/// handler and registering it here, the commands sent to Discord come from
+/// what's registered.
+#[derive(Default)]
+pub struct Registry {
+	slash: Vec<(String, Box<dyn SlashCommand>)>,
+	message: Vec<(String, Box<dyn MessageCommand>)>,
+	components: Vec<Box<dyn ComponentHandler>>,
+	modals: Vec<Box<dyn ModalHandler>>,
+}
+
+impl Registry {
+	pub fn slash(&mut self, handler: impl SlashCommand + 'static) -> &mut Self {
+		self.slash.push((handler.command().name, Box::new(handler)));
+		self
+	}
+
+	pub fn message(&mut self, handler: impl MessageCommand + 'static) -> &mut Self {
+		self.message.push((handler.name(), Box::new(handler)));
+		self
+	}
+
+	pub fn component(&mut self, handler: impl ComponentHandler + 'static) -> &mut Self {
+		self.components.push(Box::new(handler));
+		self
+	}
+
+	pub fn modal(&mut self, handler: impl ModalHandler + 'static) -> &mut Self {
+		self.modals.push(Box::new(handler));
+		self
+	}
+
+	/// The commands to register in `guild`, or globally without one.
+	pub fn commands(&self, guild: Option<Id<GuildMarker>>) -> Vec<Command> {
+		let slash = self.slash.iter().map(|(_, handler)| Command {
+			guild_id: guild,
+			default_permission: Some(true),
+			..handler.command()
+		});
+		let message = self.message.iter().map(|(name, _)| Command {
+			guild_id: guild,
+			..CommandBuilder::new(name.clone(), String::new(), CommandType::Message)
+				.default_permission(true)
+				.build()
+		});
+
+		slash.chain(message).collect()
+	}
+
+	/// Runs whichever handler an interaction is for, if whoever used it gets through `guard`.
+	pub async fn handle(&self, ctx: &Context, interaction: &Interaction) -> Result<(), BotError> {
+		match interaction {
+			Interaction::ApplicationCommand(cmd) if cmd.data.kind == CommandType::Message => {
+				let handler = match self.message.iter().find(|(name, _)| *name == cmd.data.name) {
+					Some((_, handler)) => handler,
+					None => return Ok(()),
+				};
+				let invoker = guard(ctx, cmd.guild_id, cmd.member.as_ref())?;
+
+				let message = cmd.data.target_id
+					.zip(cmd.data.resolved.as_ref())
+					.and_then(|(id, resolved)| resolved.messages.get(&id.cast()))
+					.ok_or_else(|| BotError::Validation(String::from("Couldn't find that message, try again.")))?;
+				handler.run(ctx, cmd, &invoker, message).await
+			}
+			Interaction::ApplicationCommand(cmd) => {
+				let handler = match self.slash.iter().find(|(name, _)| *name == cmd.data.name) {
+					Some((_, handler)) => handler,
+					None => return Ok(()),
+				};
+				let invoker = guard(ctx, cmd.guild_id, cmd.member.as_ref())?;
+				handler.run(ctx, cmd, &invoker).await
+			}
+			Interaction::MessageComponent(msgcmp) => {
+				let handler = match self.components.iter().find(|h| h.handles(&msgcmp.data.custom_id)) {
+					Some(handler) => handler,
+					None => return Ok(()),
+				};
+				let invoker = guard(ctx, msgcmp.guild_id, msgcmp.member.as_ref())?;
+				handler.run(ctx, msgcmp, &invoker).await
+			}
+			Interaction::ModalSubmit(modal) => {
+				let handler = match self.modals.iter().find(|h| h.handles(&modal.data.custom_id)) {
+					Some(handler) => handler,
+					None => return Ok(()),
+				};
+				let invoker = guard(ctx, modal.guild_id, modal.member.as_ref())?;
+				handler.run(ctx, modal, &invoker).await
+			}
+			_ => Ok(()),
+		}
+	}
+}
+
+/// What every interaction has to get through: it has to be in a guild the bot's
+/// set up in, by a member the cache knows about, who's `allowed` to use the bot.
+pub fn guard(ctx: &Context, guild: Option<Id<GuildMarker>>, member: Option<&PartialMember>) -> Result<Invoker, BotError> {
+	let (guild, user) = match (guild, member.and_then(|m| m.user.as_ref())) {
+		(Some(guild), Some(user)) => (guild, user),
+		_ => return Err(BotError::Validation(String::from("That only works in a server."))),
+	};
+	if config::get().guild(guild).is_none() {
+		return Err(BotError::Config(String::from("this server isn't in the config")));
+	}
+
+	let member = ctx.cache.member(guild, user.id).ok_or_else(|| BotError::Validation(String::from(NOT_CACHED)))?;
+	allowed(guild, user.id, member.roles())?;
+
+	Ok(Invoker { user: user.clone(), guild, roles: member.roles().to_vec() })
+}
+
+/// Whether someone can use the bot in `guild`: they can't have a banned role,
+/// or have been banned themselves. Everything that runs code or changes anything
+/// for someone checks this, `guard` for interactions.
+pub fn allowed(guild: Id<GuildMarker>, user: Id<UserMarker>, roles: &[Id<RoleMarker>]) -> Result<(), BotError> {
+	if roles.iter().any(|role| settings::contains(guild, Setting::BannedRole, role.get())) {
+		return Err(BotError::Permission(String::from(BANNED)));
+	}
+	if let Some(ban) = storage::get().user_ban(guild, user)? {
+		return Err(BotError::Blocked(ban.value));
+	}
+	Ok(())
+}
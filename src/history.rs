@@ -0,0 +1,252 @@
+use async_trait::async_trait;
+use tracing::error;
+use twilight_http::Client;
+use twilight_model::{
+	application::{
+		command::{self, Command, CommandOption, CommandType, NumberCommandOptionData},
+		component::{action_row::ActionRow, button::{Button, ButtonStyle}, Component},
+		interaction::{application_command::CommandOptionValue, ApplicationCommand, MessageComponentInteraction},
+	},
+	channel::message::MessageFlags,
+	http::interaction::{InteractionResponse, InteractionResponseData, InteractionResponseType},
+	id::{marker::ApplicationMarker, Id},
+};
+use twilight_util::builder::command::CommandBuilder;
+
+use crate::code::RunOptions;
+use crate::config;
+use crate::error::BotError;
+use crate::handler::{ComponentHandler, Context, Invoker, SlashCommand};
+use crate::render;
+use crate::run;
+use crate::screen;
+use crate::storage::{self, Execution, Stored};
+
+/// The most runs on a page, there's a button for each of them in a row.
+pub const MAX_PAGE_SIZE: u32 = 5;
+const PREVIEW_LINES: usize = 3;
+const PREVIEW_CHARS: usize = 200;
+/// Discord won't take more than this in a text input.
+const MAX_MODAL_CODE: usize = 4000;
+
+/// `/history`, the runs of whoever used it in the guild it's used in, newest
+/// first. Only they can see it.
+pub struct HistoryCommand;
+
+#[async_trait]
+impl SlashCommand for HistoryCommand {
+	fn command(&self) -> Command {
+		CommandBuilder::new(
+			"history".into(),
+			"See the code you've run, and run it again.".into(),
+			CommandType::ChatInput,
+		)
+		.option(CommandOption::Integer(NumberCommandOptionData {
+			autocomplete: false,
+			choices: vec![],
+			description: format!("How many runs to show at once, up to {}.", MAX_PAGE_SIZE),
+			max_value: Some(command::CommandOptionValue::Integer(MAX_PAGE_SIZE as i64)),
+			min_value: Some(command::CommandOptionValue::Integer(1)),
+			name: String::from("count"),
+			required: false,
+		}))
+		.build()
+	}
+
+	async fn run(&self, ctx: &Context, cmd: &ApplicationCommand, invoker: &Invoker) -> Result<(), BotError> {
+		let page_size = cmd.data.options.iter()
+			.find_map(|option| match option.value {
+				CommandOptionValue::Integer(count) if option.name == "count" => Some(count.clamp(1, MAX_PAGE_SIZE as i64) as u32),
+				_ => None,
+			})
+			.unwrap_or(MAX_PAGE_SIZE);
+
+		ctx.interaction()
+			.create_response(
+				cmd.id,
+				&cmd.token,
+				&InteractionResponse {
+					kind: InteractionResponseType::ChannelMessageWithSource,
+					data: Some(page(invoker, 0, page_size)),
+				}
+			)
+			.exec()
+			.await?;
+
+		Ok(())
+	}
+}
+
+/// The buttons under `/history`, with ids like `history:page:2:5`, `history:run:12` and `history:edit:12`.
+/// They can open a modal, which has to be the first response, so they don't get deferred up front.
+pub struct HistoryButtons;
+
+#[async_trait]
+impl ComponentHandler for HistoryButtons {
+	fn handles(&self, custom_id: &str) -> bool {
+		custom_id.starts_with("history:")
+	}
+
+	async fn run(&self, ctx: &Context, msgcmp: &MessageComponentInteraction, invoker: &Invoker) -> Result<(), BotError> {
+		let (http, user) = (&ctx.http, invoker.user.id);
+		let parts = msgcmp.data.custom_id.split(':').skip(1).collect::<Vec<_>>();
+		let broken = |_| BotError::Validation(String::from("That button doesn't work anymore."));
+
+		let response = match parts.as_slice() {
+			["page", number, page_size] => {
+				// The page size rides along in the id, which anyone could make up.
+				let page_size = page_size.parse::<u32>().map_err(broken)?.clamp(1, MAX_PAGE_SIZE);
+				InteractionResponse {
+					kind: InteractionResponseType::UpdateMessage,
+					data: Some(page(invoker, number.parse().map_err(broken)?, page_size)),
+				}
+			}
+			[action, id] => {
+				// Only the person whose history it is can see it, but there's no harm in checking.
+				let execution = match storage::get().execution(id.parse().map_err(broken)?)? {
+					Some(execution) if execution.value.user == user && execution.value.guild == Some(invoker.guild) => execution.value,
+					_ => return respond(http, msgcmp, ephemeral("That run isn't around anymore.")).await,
+				};
+
+				let options = match RunOptions::from_flags(execution.flags.split(',').filter(|f| !f.is_empty())) {
+					Ok(options) => options,
+					Err(_) => return respond(http, msgcmp, ephemeral("That run can't be done again.")).await,
+				};
+
+				match *action {
+					"edit" => run::modal(&options, Some(execution.code.chars().take(MAX_MODAL_CODE).collect())),
+					"run" => return run_again(ctx, msgcmp, invoker, &execution, &options).await,
+					_ => return Ok(()),
+				}
+			}
+			_ => return Ok(()),
+		};
+
+		respond(http, msgcmp, response).await
+	}
+}
+
+async fn run_again(
+	ctx: &Context,
+	msgcmp: &MessageComponentInteraction,
+	invoker: &Invoker,
+	execution: &Execution,
+	options: &RunOptions,
+) -> Result<(), BotError> {
+	run::pay(invoker, msgcmp.channel_id, 1)?;
+	if !screen::check_interaction(ctx, msgcmp.id, &msgcmp.token, invoker, msgcmp.channel_id, &execution.code, Some(*options)).await? {
+		return Ok(());
+	}
+
+	let filename = format!("{}-history.txt", invoker.user.id);
+	run::for_interaction(ctx, &msgcmp.token, invoker, msgcmp.channel_id, &execution.code, options, &filename).await
+}
+
+async fn respond(http: &Client, msgcmp: &MessageComponentInteraction, response: InteractionResponse) -> Result<(), BotError> {
+	http.interaction(Id::<ApplicationMarker>::new(config::get().bot_id))
+		.create_response(msgcmp.id, &msgcmp.token, &response)
+		.exec()
+		.await?;
+	Ok(())
+}
+
+fn ephemeral(content: &str) -> InteractionResponse {
+	InteractionResponse {
+		kind: InteractionResponseType::ChannelMessageWithSource,
+		data: Some(InteractionResponseData {
+			content: Some(content.to_string()),
+			flags: Some(MessageFlags::EPHEMERAL),
+			..Default::default()
+		}),
+	}
+}
+
+/// A page of someone's history in the guild they're in, with buttons to flip
+/// through it and to run or edit each run on it.
+fn page(invoker: &Invoker, page: u32, page_size: u32) -> InteractionResponseData {
+	let (user, guild) = (invoker.user.id, invoker.guild);
+	let (total, executions) = match storage::get().count_executions_by(user, guild)
+		.and_then(|total| Ok((total, storage::get().executions_by(user, guild, page_size, page.saturating_mul(page_size))?)))
+	{
+		Ok(found) => found,
+		Err(why) => {
+			error!("Failed to look up someone's history: {}", why);
+			(0, Vec::new())
+		}
+	};
+
+	if executions.is_empty() {
+		return InteractionResponseData {
+			content: Some(String::from("You haven't run anything yet.")),
+			components: Some(vec![]),
+			flags: Some(MessageFlags::EPHEMERAL),
+			..Default::default()
+		};
+	}
+
+	let pages = total.div_ceil(page_size);
+	let mut content = format!("**Your runs** (page {} of {})\n", page + 1, pages);
+	for execution in &executions {
+		content.push_str(&entry(execution));
+	}
+
+	let button = |label: String, custom_id: String, disabled: bool| Component::Button(Button {
+		custom_id: Some(custom_id),
+		disabled,
+		emoji: None,
+		label: Some(label),
+		style: ButtonStyle::Secondary,
+		url: None,
+	});
+
+	// Crates get run from what was uploaded, which isn't kept.
+	let crate_run = |execution: &Stored<Execution>| execution.value.flags == "crate";
+
+	let components = vec![
+		Component::ActionRow(ActionRow {
+			components: vec![
+				button(String::from("◀ Newer"), format!("history:page:{}:{}", page.saturating_sub(1), page_size), page == 0),
+				button(String::from("Older ▶"), format!("history:page:{}:{}", page + 1, page_size), page + 1 >= pages),
+			]
+		}),
+		Component::ActionRow(ActionRow {
+			components: executions.iter()
+				.map(|e| button(format!("Run again #{}", e.id), format!("history:run:{}", e.id), crate_run(e)))
+				.collect()
+		}),
+		Component::ActionRow(ActionRow {
+			components: executions.iter()
+				.map(|e| button(format!("Open in modal #{}", e.id), format!("history:edit:{}", e.id), crate_run(e)))
+				.collect()
+		}),
+	];
+
+	InteractionResponseData {
+		content: Some(content),
+		components: Some(components),
+		flags: Some(MessageFlags::EPHEMERAL),
+		..Default::default()
+	}
+}
+
+/// One run in the list: whether it worked, when, and the start of its code.
+fn entry(execution: &Stored<Execution>) -> String {
+	let mut preview = execution.value.code.lines().take(PREVIEW_LINES).collect::<Vec<_>>().join("\n");
+	if preview.len() > PREVIEW_CHARS {
+		let end = (0..=PREVIEW_CHARS).rev().find(|i| preview.is_char_boundary(*i)).unwrap_or(0);
+		preview.truncate(end);
+	}
+	if preview.len() < execution.value.code.len() {
+		preview.push_str("\n…");
+	}
+
+	format!(
+		"\n**#{}** {} <t:{}:R> `{}` {}ms\n```rust\n{}```",
+		execution.id,
+		if execution.value.success { "✅" } else { "❌" },
+		execution.created_at,
+		execution.value.flags,
+		execution.value.duration_ms,
+		render::escape(&preview)
+	)
+}
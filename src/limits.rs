@@ -0,0 +1,173 @@
+use std::{
+	collections::HashMap,
+	sync::Mutex,
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use once_cell::sync::Lazy;
+use twilight_model::id::{marker::{ChannelMarker, GuildMarker, UserMarker}, Id};
+
+use crate::config::{self, BucketConfig, RateLimits};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Scope {
+	User,
+	Channel,
+	Guild,
+}
+
+impl Scope {
+	fn limit(self, limits: &RateLimits) -> &BucketConfig {
+		match self {
+			Scope::User => &limits.user,
+			Scope::Channel => &limits.channel,
+			Scope::Guild => &limits.guild,
+		}
+	}
+}
+
+/// How full a bucket was the last time it was looked at.
+struct Bucket {
+	tokens: f64,
+	updated: Instant,
+}
+
+/// Every bucket that isn't full. A full one is the same as not having one, so
+/// they're dropped instead of kept around for everyone who's ever run anything.
+#[derive(Default)]
+struct Buckets(HashMap<(Scope, u64), Bucket>);
+
+static BUCKETS: Lazy<Mutex<Buckets>> = Lazy::new(Default::default);
+
+/// Takes a token for each run (`cost` of them, a message can have a few blocks)
+/// from the user's, channel's and guild's buckets. If any of them don't have
+/// enough nothing's taken, and it gives back how long until they all do.
+pub fn take(user: Id<UserMarker>, channel: Id<ChannelMarker>, guild: Option<Id<GuildMarker>>, cost: u32) -> Result<(), Duration> {
+	let mut keys = vec![(Scope::User, user.get()), (Scope::Channel, channel.get())];
+	if let Some(guild) = guild {
+		keys.push((Scope::Guild, guild.get()));
+	}
+
+	BUCKETS.lock().unwrap().take(&keys, cost, &config::get().rate_limits, Instant::now())
+}
+
+impl Buckets {
+	fn take(&mut self, keys: &[(Scope, u64)], cost: u32, limits: &RateLimits, now: Instant) -> Result<(), Duration> {
+		self.0.retain(|(scope, _), bucket| refill(bucket, scope.limit(limits), now) < scope.limit(limits).burst as f64);
+
+		let keys = keys.iter().filter(|(scope, _)| scope.limit(limits).per_minute > 0);
+		let mut wait = Duration::ZERO;
+		for &(scope, id) in keys.clone() {
+			let limit = scope.limit(limits);
+			let bucket = self.0.entry((scope, id)).or_insert(Bucket { tokens: limit.burst as f64, updated: now });
+			bucket.tokens = refill(bucket, limit, now);
+			bucket.updated = now;
+
+			// More runs than the bucket holds would never fit, those take all of it.
+			let needed = cost.min(limit.burst) as f64;
+			if bucket.tokens < needed {
+				wait = wait.max(Duration::from_secs_f64((needed - bucket.tokens) * 60.0 / limit.per_minute as f64));
+			}
+		}
+
+		if !wait.is_zero() {
+			return Err(wait);
+		}
+
+		for &(scope, id) in keys {
+			if let Some(bucket) = self.0.get_mut(&(scope, id)) {
+				bucket.tokens -= cost.min(scope.limit(limits).burst) as f64;
+			}
+		}
+		Ok(())
+	}
+}
+
+fn refill(bucket: &Bucket, limit: &BucketConfig, now: Instant) -> f64 {
+	let minutes = now.duration_since(bucket.updated).as_secs_f64() / 60.0;
+	(bucket.tokens + minutes * limit.per_minute as f64).min(limit.burst as f64)
+}
+
+/// What to tell someone who's been rate limited.
+pub fn cooldown(wait: Duration) -> String {
+	let until = SystemTime::now() + wait + Duration::from_secs(1);
+	let until = until.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+	format!("⏰ Slow down! You can run more code <t:{}:R>.", until)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn limits(burst: u32, per_minute: u32) -> RateLimits {
+		let limit = BucketConfig { burst, per_minute };
+		// Channels are roomy but slow to refill, so they're never what runs out first.
+		RateLimits { user: limit.clone(), channel: BucketConfig { burst: 100, per_minute: 1 }, guild: limit }
+	}
+
+	const KEYS: &[(Scope, u64)] = &[(Scope::User, 1), (Scope::Channel, 2)];
+
+	#[test]
+	fn burst_then_refill() {
+		let (limits, start) = (limits(2, 6), Instant::now());
+		let mut buckets = Buckets::default();
+		assert!(buckets.take(KEYS, 1, &limits, start).is_ok());
+		assert!(buckets.take(KEYS, 1, &limits, start).is_ok());
+
+		// Empty, and 6 a minute is one every 10 seconds.
+		let wait = buckets.take(KEYS, 1, &limits, start).unwrap_err();
+		assert_eq!(wait.as_secs(), 10);
+		assert!(buckets.take(KEYS, 1, &limits, start + Duration::from_secs(10)).is_ok());
+	}
+
+	#[test]
+	fn nothing_taken_when_refused() {
+		let (limits, now) = (limits(2, 6), Instant::now());
+		let mut buckets = Buckets::default();
+		assert!(buckets.take(KEYS, 2, &limits, now).is_ok());
+		assert!(buckets.take(&[(Scope::User, 1), (Scope::Channel, 3)], 1, &limits, now).is_err());
+
+		// The user's bucket was empty, so channel 3's didn't lose anything either.
+		assert_eq!(buckets.0[&(Scope::Channel, 3)].tokens, 100.0);
+	}
+
+	#[test]
+	fn costs_a_token_per_run() {
+		let (limits, now) = (limits(5, 6), Instant::now());
+		let mut buckets = Buckets::default();
+		assert!(buckets.take(KEYS, 3, &limits, now).is_ok());
+		assert_eq!(buckets.take(KEYS, 3, &limits, now).unwrap_err().as_secs(), 10);
+		assert!(buckets.take(KEYS, 2, &limits, now).is_ok());
+	}
+
+	#[test]
+	fn more_runs_than_the_burst_take_all_of_it() {
+		let (limits, now) = (limits(2, 6), Instant::now());
+		let mut buckets = Buckets::default();
+		assert!(buckets.take(KEYS, 5, &limits, now).is_ok());
+		assert!(buckets.take(KEYS, 1, &limits, now).is_err());
+	}
+
+	#[test]
+	fn zero_per_minute_is_no_limit() {
+		let (limits, now) = (limits(1, 0), Instant::now());
+		let mut buckets = Buckets::default();
+		for _ in 0..10 {
+			assert!(buckets.take(&[(Scope::User, 1)], 1, &limits, now).is_ok());
+		}
+		assert!(buckets.0.is_empty());
+	}
+
+	#[test]
+	fn full_buckets_are_dropped() {
+		let (limits, start) = (limits(2, 6), Instant::now());
+		let mut buckets = Buckets::default();
+		assert!(buckets.take(KEYS, 1, &limits, start).is_ok());
+		assert_eq!(buckets.0.len(), 2);
+
+		// Ten seconds later the user's is full again, the busier channel's isn't.
+		assert!(buckets.take(&[], 1, &limits, start + Duration::from_secs(10)).is_ok());
+		assert!(!buckets.0.contains_key(&(Scope::User, 1)));
+		assert!(buckets.0.contains_key(&(Scope::Channel, 2)));
+	}
+}
@@ -1,43 +1,84 @@
 // use futures_util::{TryStreamExt,StreamExt};
 use futures::StreamExt;
 
-use hyper::body::Buf;
-use hyper::{client::HttpConnector, Body, Client as HyperClinet, Request};
-use hyper_tls::HttpsConnector;
 use tracing_subscriber::FmtSubscriber;
-use twilight_http::request::AuditLogReason;
-use twilight_model::application::component::text_input::TextInputStyle;
-use twilight_model::application::interaction::application_command::CommandOptionValue;
-use twilight_model::id::marker::{GuildMarker, MessageMarker};
-use twilight_util::builder::embed::{EmbedBuilder, ImageSource};
+use twilight_model::id::marker::GuildMarker;
 use std::{
     error::Error,
+    path::{Path, PathBuf},
     sync::{Arc},
 };
 
 use tokio::{task};
 use tracing::{error, info, Level};
-use twilight_cache_inmemory::{InMemoryCache, InMemoryCacheBuilder, ResourceType};
+use twilight_cache_inmemory::{InMemoryCacheBuilder, ResourceType};
 use twilight_gateway::{cluster::ClusterBuilder, Event, Intents};
 use twilight_http::{request::channel::reaction::RequestReactionType, Client};
 use twilight_model::{
     id::{
-        marker::{RoleMarker, ChannelMarker,ApplicationMarker},
+        marker::{ApplicationMarker, RoleMarker},
         Id,
     },
-	application::{component::{Component,action_row::ActionRow,button::{Button,ButtonStyle},TextInput},command::{CommandType,CommandOption, ChoiceCommandOptionData, BaseCommandOptionData}},
-	http::{interaction::{InteractionResponse,InteractionResponseType,InteractionResponseData}, attachment::Attachment},
-	channel::message::MessageFlags
+	channel::Message
 };
-use twilight_util::builder::{command::{BooleanBuilder, CommandBuilder, StringBuilder},embed::EmbedAuthorBuilder};
 
+mod admin;
+mod attachment;
+mod audit;
+mod backend;
+mod code;
+mod compile;
 mod config;
+mod error;
+mod handler;
+mod history;
+mod limits;
+mod paste;
 mod play;
+mod playground;
+mod project;
+mod queue;
+mod render;
+mod replies;
+mod roles;
+mod run;
+mod screen;
+mod settings;
+mod storage;
+
+use backend::ExecutionBackend;
+use code::RunOptions;
+use error::BotError;
+use handler::{Context, Registry};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     dotenv::dotenv().ok();
 
+	let args = match Args::parse(std::env::args().skip(1)) {
+		Ok(args) => args,
+		Err(why) => {
+			eprintln!("{}\n\n{}", why, USAGE);
+			std::process::exit(2);
+		}
+	};
+
+	if args.help {
+		println!("{}", USAGE);
+		return Ok(());
+	}
+
+	if args.check_config {
+		match config::load(&args.config) {
+			Ok(config) => print!("{}", config.redacted()),
+			Err(why) => {
+				eprintln!("{}", why);
+				std::process::exit(1);
+			}
+		}
+		return Ok(());
+	}
+
 	let subscriber = FmtSubscriber::builder()
 		.with_max_level(Level::INFO)
 		.finish();
@@ -46,7 +87,19 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     info!("Starting up bot");
 
-    let token = config::CONFIG.token.clone();
+	if let Err(why) = config::init(&args.config) {
+		error!("{}", why);
+		std::process::exit(1);
+	}
+	task::spawn(config::watch(args.config));
+
+	let data_dir = config::get().data_dir.clone();
+	if let Err(why) = storage::init(Path::new(&data_dir)) {
+		error!("Couldn't open the database in {}: {}", data_dir, why);
+		std::process::exit(1);
+	}
+
+    let token = config::get().token.clone();
 
     let cache = Arc::new(
         InMemoryCacheBuilder::new()
@@ -60,63 +113,20 @@ async fn main() -> Result<(), Box<dyn Error>> {
     );
     let http = Arc::new(Client::builder().token(token.clone()).build());
 
-	let commands = &[
-		CommandBuilder::new(
-			"run".into(),
-			"Run some Rust out side of defined run-my-rust channels.".into(),
-			CommandType::ChatInput,
-		).guild_id(Id::<GuildMarker>::new(config::CONFIG.server_id)).default_permission(true).build(),
-		CommandBuilder::new(
-			"project".into(),
-			"Show off a neat project of yours!".into(),
-			CommandType::ChatInput,
-		)
-		.option(
-			CommandOption::String(ChoiceCommandOptionData {
-			    autocomplete: false,
-			    choices: vec![],
-			    description: String::from("Whats the name of your project?"),
-			    name: String::from("name"),
-			    required: true,
-			})
-		)
-		.option(
-			CommandOption::String(ChoiceCommandOptionData {
-			    autocomplete: false,
-			    choices: vec![],
-			    description: String::from("Whats your project's description? Max of 1000 characters. Keep it short and simple."),
-			    name: String::from("description"),
-			    required: false,
-			})
-		)
-		.option(
-			CommandOption::Boolean(BaseCommandOptionData {
-			    description: String::from("Is this project on crates.io? Make sure the name you set is the same on crates.io"),
-			    name: String::from("crates-io"),
-			    required: false,
-			})
-		)
+	let registry = Arc::new(registry());
+	register_commands(&http, &registry).await?;
 
-		.option(
-			CommandOption::String(ChoiceCommandOptionData {
-			    autocomplete: false,
-			    choices: vec![],
-			    description: String::from("Does your project have a github repo?"),
-			    name: String::from("github"),
-			    required: false,
-			}))
-		.guild_id(Id::<GuildMarker>::new(config::CONFIG.server_id)).default_permission(true).build(),
-		CommandBuilder::new(
-			"run-message".into(),
-			"".into(),
-			CommandType::Message,
-		).guild_id(Id::<GuildMarker>::new(config::CONFIG.server_id)).default_permission(true).build()
-	];
-	http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-		.set_guild_commands(Id::<GuildMarker>::new(config::CONFIG.server_id), commands).exec().await.unwrap();
-
-    let hyper_cline =
-        Arc::new(HyperClinet::builder().build::<_, hyper::Body>(HttpsConnector::new()));
+	let ctx = Arc::new(Context {
+		http: http.clone(),
+		cache: cache.clone(),
+		backend: backend::from_config(&config::get().backend),
+	});
+
+	if let config::BackendConfig::Local(local) = &config::get().backend {
+		if let Some(bind) = &local.paste_bind {
+			task::spawn(paste::serve(bind.parse()?, local.paste_dir.clone().into()));
+		}
+	}
 	
 	let (cluster, mut events) = ClusterBuilder::new(
 			token,
@@ -136,94 +146,267 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
 	let http_clone = http.clone();
 	task::spawn(async move {
-		let http = http_clone.clone();
-
-		if config::CONFIG.settings.send_on_start {
-			for button_menu in &config::CONFIG.button_menus {
-				let channel_id = Id::<ChannelMarker>::new(button_menu.channel_id);
-				let channel_messages = http
-					.channel_messages(channel_id)
-					.limit(config::CONFIG.settings.messages_to_check as u16).unwrap()
-					.exec()
-					.await.unwrap();
-
-				for message in channel_messages.models().await.unwrap() {
-					if message.author.bot && message.author.id.get() == config::CONFIG.bot_id {
-						if message.content == button_menu.message {
-							match http.delete_message(channel_id, message.id).reason("Sending new button menu").unwrap().exec().await {
-								Ok(_) => info!("Should've deleted?"),
-								Err(_) => error!("Failed to delete a bot's message while starting"),
-							}
-						}
-						continue;
-					}
-
-					match http.create_message(channel_id)
-						.content(&button_menu.message).unwrap()
-						.components(&[
-							Component::ActionRow(
-								ActionRow {
-									components: button_menu.roles.iter().map(|r| {
-										let role_label = format!("{}", r.id);
-										Component::Button(Button {
-									        custom_id: Some(role_label),
-									        disabled: false,
-									        emoji: None,
-									        label: Some(r.label.clone()),
-									        style: match r.style {
-												1 => ButtonStyle::Primary,
-												2 => ButtonStyle::Secondary,
-												3 => ButtonStyle::Success,
-												4 => ButtonStyle::Danger,
-												_ => ButtonStyle::Secondary
-											},
-									        url: None,
-									    })
-									}).collect::<Vec<Component>>()
-								})]
-						).unwrap().exec().await {
-					        Ok(_) => {},
-					        Err(_) => error!("Failed to send a button menu."),
-					    }
-					break;
-				}
-				
+		if config::get().settings.send_on_start {
+			if let Err(why) = roles::send_button_menus(&http_clone).await {
+				error!("Failed to send the button menus: {}", why);
 			}
 		}
 	});
 
     while let Some(event) = events.next().await {
-		// cache.update(&event.1);
-        let cache = cache.clone();
-        let http = http.clone();
-        let hyper = hyper_cline.clone();
+        let ctx = ctx.clone();
+        let registry = registry.clone();
 
         tokio::spawn(async move {
-            if handle_event(cache, http.clone(), event.clone(), hyper).await.is_err() {
-                error!("Error handling event");
-				match event.1 {
-					Event::MessageCreate(message) => {
-						let _message = http
-							.create_message(message.channel_id)
-							.content(":x: there was an error handling that event. please repooort me.")
-							.unwrap()
-							.exec()
-							.await;
-					}
-					_ => error!("Error from event we dont even handle...")
-				}
-            };
+            if let Err(why) = handle_event(&ctx, &registry, event.clone()).await {
+                error::report(&ctx.http, &event.1, why).await;
+            }
         });
     }
     Ok(())
 }
 
+const USAGE: &str = "usage: run_my_rust [check-config] [--config <path>]
+
+  check-config      check the config and print it (with the token left out)
+                    instead of starting the bot
+  --config <path>   where the config is, config.toml by default
+
+Fields in the config can be set with RMR_ environment variables too, like
+RMR_TOKEN for `token` or RMR_BACKEND__URL for `url` under `[backend]`.";
+
+/// What the bot was started with.
+struct Args {
+	config: PathBuf,
+	check_config: bool,
+	help: bool,
+}
+
+impl Args {
+	fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+		let mut parsed = Args { config: PathBuf::from("config.toml"), check_config: false, help: false };
+		while let Some(arg) = args.next() {
+			match arg.as_str() {
+				"check-config" => parsed.check_config = true,
+				"-h" | "--help" => parsed.help = true,
+				"--config" => parsed.config = args.next().ok_or("--config needs a path")?.into(),
+				_ => return Err(format!("don't know what `{}` is", arg)),
+			}
+		}
+		Ok(parsed)
+	}
+}
+
+/// Every command, button and modal the bot has.
+fn registry() -> Registry {
+	let mut registry = Registry::default();
+	registry
+		.slash(run::RunCommand)
+		.slash(project::ProjectCommand)
+		.slash(history::HistoryCommand)
+		.slash(admin::AdminCommand)
+		.message(run::RunMessage)
+		.modal(run::RunModal)
+		.modal(compile::CompileModal)
+		.component(history::HistoryButtons)
+		.component(run::ShareButton)
+		.component(roles::RoleButtons)
+		.component(screen::ConfirmButtons);
+
+	for target in code::Target::ALL {
+		registry.slash(compile::CompileCommand(target));
+		if target.has_message_command() {
+			registry.message(compile::CompileMessage(target));
+		}
+	}
+
+	registry
+}
+
+/// Registers the commands in each guild, or globally. Whichever isn't used gets
+/// cleared, so switching between them doesn't leave every command there twice.
+async fn register_commands(http: &Client, registry: &Registry) -> Result<(), BotError> {
+	let config = config::get();
+	let interaction = http.interaction(Id::<ApplicationMarker>::new(config.bot_id));
+
+	let global = match config.commands {
+		config::CommandScope::Guild => Vec::new(),
+		config::CommandScope::Global => registry.commands(None),
+	};
+	interaction.set_global_commands(&global).exec().await?;
+
+	for &guild in config.guilds.keys() {
+		let guild = Id::<GuildMarker>::new(guild);
+		let commands = match config.commands {
+			config::CommandScope::Guild => registry.commands(Some(guild)),
+			config::CommandScope::Global => Vec::new(),
+		};
+		interaction.set_guild_commands(guild, &commands).exec().await?;
+	}
+
+	Ok(())
+}
+
+/// Runs the code in a message in one of the run channels, posting the results in
+/// a thread on it. If the message has been run before (it was edited), the old
+/// results get edited instead.
+async fn run_in_thread(
+	http: &Client,
+	backend: &Arc<dyn ExecutionBackend>,
+	message: &Message,
+	guild: Id<GuildMarker>,
+	roles: &[Id<RoleMarker>],
+) -> Result<(), BotError> {
+	let mut replies = replies::ThreadReplies::new(message);
+	// However the run ends, what it posted has to be remembered and what's left
+	// from before an edit cleaned up, or the next edit would post it all again.
+	let result = run_message(http, backend, message, guild, roles, &mut replies).await;
+	replies.finish(http).await;
+	result
+}
+
+async fn run_message(
+	http: &Client,
+	backend: &Arc<dyn ExecutionBackend>,
+	message: &Message,
+	guild: Id<GuildMarker>,
+	roles: &[Id<RoleMarker>],
+	replies: &mut replies::ThreadReplies<'_>,
+) -> Result<(), BotError> {
+	let loading = RequestReactionType::Unicode { name: "🌀" };
+	let failed = RequestReactionType::Unicode { name: "❌" };
+	let success = RequestReactionType::Unicode { name: "✅" };
+
+	let blocks = code::blocks(&message.content);
+	let uploads = attachment::runnable(&message.attachments);
+
+	// An edit that took the code out only has its old results cleaned up.
+	if blocks.is_empty() && uploads == 0 {
+		return Ok(());
+	}
+
+	handler::allowed(guild, message.author.id, roles)?;
+
+	// Each block (or upload) that'll be run costs a run. Uploads are only
+	// downloaded once they're paid for.
+	let runs = (blocks.len() + uploads).min(code::MAX_BLOCKS) as u32;
+	if limits::take(message.author.id, message.channel_id, Some(guild), runs).is_err() {
+		http.create_reaction(message.channel_id, message.id, &RequestReactionType::Unicode { name: "⏰" })
+			.exec()
+			.await?;
+		return Ok(());
+	}
+
+	let (mut blocks, crates, problems) = run::split_uploads(blocks, attachment::uploads(&message.attachments).await);
+
+	http.create_reaction(message.channel_id, message.id, &loading)
+		.exec()
+		.await?;
+
+	// Waiting in line gets a note saying where, taken down once it's this message's turn.
+	let mut ticket = queue::join(message.author.id);
+	let mut note = None;
+	let _permit = loop {
+		match ticket.turn().await {
+			queue::Turn::Ready(permit) => break permit,
+			queue::Turn::Queued(position) => {
+				let content = format!("🌀 Queued #{}, hang on...", position);
+				note = Some(match note {
+					Some(note) => {
+						http.update_message(message.channel_id, note).content(Some(&content))?.exec().await?;
+						note
+					}
+					None => http.create_message(message.channel_id)
+						.reply(message.id)
+						.content(&content)?
+						.exec()
+						.await?
+						.model()
+						.await?
+						.id,
+				});
+			}
+		}
+	};
+	if let Some(note) = note {
+		http.delete_message(message.channel_id, note).exec().await?;
+	}
+
+	for why in &problems {
+		http.create_reaction(message.channel_id, message.id, &failed)
+			.exec()
+			.await?;
+		replies.notice(http, why).await?;
+	}
+
+	blocks.truncate(code::MAX_BLOCKS);
+	let crates = crates.into_iter().take(code::MAX_BLOCKS - blocks.len());
+
+	for block in blocks {
+		let options = match RunOptions::from_flags(block.flags) {
+			Ok(options) => options,
+			Err(why) => {
+				http.create_reaction(message.channel_id, message.id, &failed)
+					.exec()
+					.await?;
+				replies.notice(http, &why).await?;
+				continue;
+			}
+		};
+		if !screen::check_message(http, replies, message, guild, &block.code, Some(options)).await? {
+			continue;
+		}
+
+		let playground = play::Playground::new(&block.code, &options);
+
+		let ran_by = run::RanBy { user: message.author.id, guild: Some(guild), channel: message.channel_id };
+		let (content, execution) = match run::code(backend, &block.code, &playground, &options, ran_by).await {
+			Ok(ran) => ran,
+			Err(why) => {
+				http.create_reaction(message.channel_id, message.id, &failed)
+					.exec()
+					.await?;
+				replies.notice(http, &run::failed(why)).await?;
+				continue;
+			}
+		};
+
+		replies.post(http, &content, &run::share_button(backend, &playground, execution).await).await?;
+	}
+
+	for krate in crates {
+		if !screen::check_message(http, replies, message, guild, &screen::crate_code(&krate), None).await? {
+			continue;
+		}
+
+		let krate = play::Crate::new(krate, &RunOptions::default());
+		let ran_by = run::RanBy { user: message.author.id, guild: Some(guild), channel: message.channel_id };
+		match run::krate(backend, &krate, ran_by).await {
+			Ok(content) => replies.post(http, &content, &run::crate_share_button(backend, &krate).await).await?,
+			Err(why) => {
+				http.create_reaction(message.channel_id, message.id, &failed)
+					.exec()
+					.await?;
+				replies.notice(http, &run::failed(why)).await?;
+			}
+		}
+	}
+
+	if replies.any() {
+		http.create_reaction(message.channel_id, message.id, &success)
+			.exec()
+			.await?;
+	}
+
+	Ok(())
+}
+
 async fn handle_event(
-    cache: Arc<InMemoryCache>,
-    http: Arc<Client>,
+    ctx: &Context,
+    registry: &Registry,
     (shard_id, event): (u64, Event),
-    http2: Arc<HyperClinet<HttpsConnector<HttpConnector>>>,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<(), BotError> {
+    let Context { http, cache, backend } = ctx;
     cache.update(&event);
 
     match event {
@@ -231,739 +414,64 @@ async fn handle_event(
             info!("Shard {} is now ready", shard_id);
         }
 		Event::InteractionCreate(interaction) => {
-			match &interaction.0 {
-				twilight_model::application::interaction::Interaction::ModalSubmit(modal) => {
-					let guild_id = match interaction.guild_id() {
-				        Some(g) =>g,
-				        None => {return Ok(())}
-				    };
-
-					// println!("{:#?}", modal);
-					http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-						.create_response(
-							modal.id, 
-							&modal.token,
-							&InteractionResponse {
-								kind: InteractionResponseType::DeferredChannelMessageWithSource,
-								data: None,
-				
-							}
-						)
-						.exec().await.unwrap();
-
-					if let Some(member) = &modal.member {
-						if let Some(user) = &member.user {
-							if cache.member(guild_id, user.id).is_none() {
-								http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-									.create_followup(
-										&modal.token,
-									)
-									.content("You're not cached. Send a message somewhere and press me again.").unwrap()
-									.flags(MessageFlags::EPHEMERAL)
-									.exec()
-									.await.unwrap();
-
-								return Ok(());							
-							}
-
-							let member = cache.member(guild_id, user.id).unwrap();
-							for role in &config::CONFIG.banned_roles {
-								if member.roles().contains(&Id::<RoleMarker>::new(*role)) {
-									info!("Banned user tried running getting a role");
-									return Ok(());
-								}
-							}
-
-							let mut comps = modal.data.components.iter();
-							while let Some(action_row) = comps.next() {
-								for comp in &action_row.components {
-									if modal.data.custom_id == String::from("run-my-rust") {
-										match comp.custom_id.as_str() {
-											"code-to-run" => {
-												let content = comp.value.clone();
-												let playground = play::Playground {
-													channel: "stable".to_string(),
-													mode: "debug".to_string(),
-													edition: "2021".to_string(),
-													backtrace: false,
-													tests: false,
-													crate_type: "bin".to_string(),
-													code: if content.contains("fn main()") {
-														content
-													} else {
-														format!(
-															"fn main() {{ println!(\"{{:?}}\", {{ {} }} ) }}",
-															content
-														)
-													},
-												};
-					
-												let request = Request::builder()
-													.uri("https://play.rust-lang.org/execute")
-													.method("POST")
-													.header("User-Agent", "RunMyRust/1.0")
-													.header("Content-Type", "application/json")
-													.body(Body::from(serde_json::to_vec(&playground)?))?;
-					
-												let response = match http2.request(request).await {
-													Ok(r) => r,
-													Err(_) => return Ok(())
-												};
-					
-												let body = hyper::body::aggregate(response).await?;
-												let response: play::PlaygroundResult = serde_json::from_reader(body.reader())?;
-
-												let content = &format!(
-													"Result: {}\nStds:\n**Out:** {}\n**Err:** ```{}```",
-													response.success,
-													response.stdout,
-													response.stderr.replace('`', "\"")
-												);
-												
-												if content.len() > 2000 {
-													http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-													.create_followup(
-														&modal.token,
-													)
-													.content("<:ferrisbanne:958831785922416780> The output was too long. So here you go. Have an attachment. Is this what you wanted?").unwrap()
-													.attachments(&[
-														Attachment { 
-															description: None, 
-															file: content.clone().as_bytes().to_vec(), 
-															filename: format!("{}-{}.txt", guild_id.get(), user.id.get())
-														}
-													]).unwrap()
-													.exec()
-													.await.unwrap();
-												} else {
-													http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-													.create_followup(
-														&modal.token,
-													)
-													.content(content).unwrap()
-													.exec()
-													.await.unwrap();
-												}				
-											}
-											_ => continue
-										}
-									}
-								}
-							}
-						}
-					}
-				}
-				twilight_model::application::interaction::Interaction::ApplicationCommand(cmd) => {
-					match cmd.data.name.as_str() {
-						"project" => {
-							let guild_id = match interaction.guild_id() {
-								Some(g) =>g,
-								None => {return Ok(())}
-							};
-
-							if let Some(channel_id) = config::CONFIG.projects_channel {						
-								if let Some(member) = &cmd.member {
-									if let Some(user) = &member.user {
-										if cache.member(cmd.guild_id.unwrap(), user.id).is_none() {
-											http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-												.create_followup(
-													&cmd.token,
-												)
-												.content("You're not cached. Send a message somewhere and press me again.").unwrap()
-												.flags(MessageFlags::EPHEMERAL)
-												.exec()
-												.await.unwrap();
-			
-											return Ok(());							
-										}
-
-										let member = cache.member(guild_id, user.id).unwrap();
-										for role in &config::CONFIG.banned_roles {
-											if member.roles().contains(&Id::<RoleMarker>::new(*role)) {
-												info!("Banned user tried running getting a role");
-												return Ok(());
-											}
-										}
-		
-
-										http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-										.create_response(
-											cmd.id, 
-											&cmd.token,
-											&InteractionResponse {
-												kind: InteractionResponseType::DeferredChannelMessageWithSource,
-												data: None,
-								
-											}
-										)
-										.exec().await.unwrap();
-
-										let name = match &cmd.data.options.iter().filter(|e| e.name == "name" ).next().unwrap().value {
-											CommandOptionValue::String(string) => string,
-											_ => {
-												http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-													.create_followup(
-														&cmd.token,
-													)
-													.content("That name you provided was mangled by Discord?").unwrap()
-													.flags(MessageFlags::EPHEMERAL)
-													.exec()
-													.await.unwrap();
-												return Ok(())
-											}
-										};
-
-										let github = cmd.data.options.iter().filter(|e| e.name == "github" ).next();
-										let description = cmd.data.options.iter().filter(|e| e.name == "description" ).next();
-										let crates_io = cmd.data.options.iter().filter(|e| e.name == "crates-io" ).next();
-										
-										let mut comps = Vec::new();
-
-										if let Some(crates_io) = crates_io {
-											match crates_io.value {
-									            CommandOptionValue::Boolean(crates) => if crates {
-													comps.push(Component::Button(Button {
-										                custom_id: None,
-										                disabled: false,
-										                emoji: None,
-										                label: Some("view on crates.io".to_string()),
-										                style: ButtonStyle::Link,
-										                url: Some(format!("https://crates.io/crates/{}", name)),
-										            }))
-												},
-									            _ => {}
-									        }
-										}
-
-										if let Some(github) = github {
-											match &github.value {
-									            CommandOptionValue::String(github) => {
-													comps.push(Component::Button(Button {
-										                custom_id: None,
-										                disabled: false,
-										                emoji: None,
-										                label: Some("view on github".to_string()),
-										                style: ButtonStyle::Link,
-										                url: Some(github.to_string()),
-										            }))
-												},
-									            _ => {}
-									        }											
-										}
-
-										let author = EmbedAuthorBuilder::new(format!("{}#{}", user.name, user.discriminator()));
-										
-										let author = if let Some(avatar) = user.avatar {
-											match ImageSource::url(format!(
-												"https://cdn.discordapp.com/avatars/{}/{}.png",
-												user.id, avatar
-											)) {
-												Ok(icon_url) => {
-													author.icon_url(icon_url)
-												},
-												Err(_) => author
-											}
-										} else { author };
-
-										let embed = EmbedBuilder::new()
-											.author(author.build())
-											.title(name)
-											.color(11237454);
-
-										let embed = if let Some(description) = description {
-											match &description.value {
-									            CommandOptionValue::String(description) => {
-													if description.len() > 1000 || description.len() < 1 {
-														http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-														.create_followup(
-															&cmd.token,
-														)
-														.content("Sorry your description is either too large or too small. Remmber it cannot be above 1000 characters and must be above 1 character.").unwrap()
-														.flags(MessageFlags::EPHEMERAL)
-														.exec()
-														.await.unwrap();
-														return Ok(())
-													}
-
-													embed.description(description)
-												},
-									            _ => embed
-									        }
-										} else {
-											embed
-										};
-										let embed = embed.validate().unwrap();
-										let embed = vec![embed.build()];
-
-										match http.create_message(Id::<ChannelMarker>::new(channel_id))
-											.embeds(&embed).unwrap()
-											.content("A new project has been discovered!").unwrap()
-											.components(&[Component::ActionRow(ActionRow {
-												components: comps
-											})]).unwrap()
-											.exec().await {
-												Ok(message) => match message.model().await {
-													Ok(m) => {
-														match http.create_thread_from_message(m.channel_id, m.id, &name).unwrap().exec().await {
-															_ => {}
-														}
-													},
-													Err(_) => {}
-												}
-												Err(_) => {}
-											}
-										
-										http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-											.create_followup(
-												&cmd.token,
-											)
-											.content("👍").unwrap()
-											.flags(MessageFlags::EPHEMERAL)
-											.exec()
-											.await.unwrap();
-									}
-								}
-							}
-						}
- 						"run-message" => {
-							let guild_id = match interaction.guild_id() {
-								Some(g) =>g,
-								None => {return Ok(())}
-							};
-
-							http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-								.create_response(
-									cmd.id, 
-									&cmd.token,
-									&InteractionResponse {
-										kind: InteractionResponseType::DeferredChannelMessageWithSource,
-										data: None,
-						
-									}
-								)
-								.exec().await.unwrap();
-
-							
-							let loading = RequestReactionType::Unicode { name: "🌀" };
-							let failed = RequestReactionType::Unicode { name: "❌" };
-							let success = RequestReactionType::Unicode { name: "✅" };
-							let warning = RequestReactionType::Unicode { name: "⚠️" };
-
-							if let (Some(target_id), Some(messages)) = (cmd.data.target_id,cmd.data.resolved.as_ref()) {
-								if cmd.guild_id.is_none() {
-									http.create_reaction(cmd.channel_id, target_id.cast::<MessageMarker>(), &warning)
-										.exec()
-										.await?;
-										return Ok(())
-								}
-
-								let message = match messages.messages.get(&target_id.cast::<MessageMarker>()) {
-									Some(message) => {
-										message
-									},
-									None => {
-										http.create_reaction(cmd.channel_id, target_id.cast::<MessageMarker>(), &warning)
-										.exec()
-										.await?;
-										return Ok(())
-									},
-								};
-								
-								if let Some(member) = &cmd.member {
-									if let Some(user) = &member.user {
-										if cache.member(cmd.guild_id.unwrap(), user.id).is_none() {
-											http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-												.create_followup(
-													&cmd.token,
-												)
-												.content("You're not cached. Send a message somewhere and press me again.").unwrap()
-												.flags(MessageFlags::EPHEMERAL)
-												.exec()
-												.await.unwrap();
-			
-											return Ok(());							
-										}
-										
-										let member = cache.member(cmd.guild_id.unwrap(), user.id).unwrap();
-										for role in &config::CONFIG.banned_roles {
-											if member.roles().contains(&Id::<RoleMarker>::new(*role)) {
-												info!("Banned user tried running getting a role");
-												return Ok(());
-											}
-										}
-
-										let mut message_content = message.content.split("```").peekable();
-										if message_content.peek().is_some() && message_content.next().unwrap().is_empty() {
-											if let Some(content) = message_content.next() {
-												let mut splitted_newlines = content.split('\n').peekable();
-												// If the message code black isn't `rs` or `rust` then just ignore it.
-												if splitted_newlines.peek().is_some()
-													&& splitted_newlines.next().unwrap()  == "rs"
-													|| splitted_newlines.next().unwrap() == "rust"
-												{
-													http.create_reaction(cmd.channel_id, target_id.cast::<MessageMarker>(), &loading)
-														.exec()
-														.await?;
-						
-													let playground = play::Playground {
-														channel: "stable".to_string(),
-														mode: "debug".to_string(),
-														edition: "2021".to_string(),
-														backtrace: false,
-														tests: false,
-														crate_type: "bin".to_string(),
-														code: if content.contains("fn main()") {
-															splitted_newlines.collect::<Vec<&str>>().join("\n")
-														} else {
-															format!(
-																"fn main() {{ println!(\"{{:?}}\", {{ {} }} ) }}",
-																splitted_newlines.collect::<Vec<&str>>().join("\n")
-															)
-														},
-													};
-						
-													let request = Request::builder()
-														.uri("https://play.rust-lang.org/execute")
-														.method("POST")
-														.header("User-Agent", "RunMyRust/1.0")
-														.header("Content-Type", "application/json")
-														.body(Body::from(serde_json::to_vec(&playground)?))?;
-						
-													let response = match http2.request(request).await {
-														Ok(r) => r,
-														Err(_) => {
-															http.create_reaction(cmd.channel_id, target_id.cast::<MessageMarker>(), &failed)
-																.exec()
-																.await?;
-															return Ok(());
-														}
-													};
-						
-													let body = hyper::body::aggregate(response).await?;
-													let response: play::PlaygroundResult =
-														serde_json::from_reader(body.reader())?;
-													// if response.success == false {
-													http.create_reaction(cmd.channel_id, target_id.cast::<MessageMarker>(), &success)
-														.exec()
-														.await?;
-													
-													let content = &format!(
-														"Result: {}\nStds:\n**Out:** {}\n**Err:** ```{}```",
-														response.success,
-														response.stdout,
-														response.stderr.replace('`', "\"")
-													);
-		
-													if content.len() > 2000 {
-														http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-														.create_followup(
-															&cmd.token,
-														)
-														.content("<:ferrisbanne:958831785922416780> The output was too long. So here you go. Have an attachment. Is this what you wanted?").unwrap()
-														.attachments(&[
-															Attachment { 
-																description: None, 
-																file: content.clone().as_bytes().to_vec(), 
-																filename: format!("{}-{}.txt", cmd.guild_id.unwrap().get(), user.id.get())
-															}
-														]).unwrap()
-														.exec()
-														.await.unwrap();
-													} else {
-														http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-														.create_followup(
-															&cmd.token,
-														)
-														.content(content).unwrap()
-														.exec()
-														.await.unwrap();
-													}	
-												}
-											}
-
-									}
-								}
-							}
-							}													
-						}
-						"run" => {
-							let guild_id = match interaction.guild_id() {
-								Some(g) =>g,
-								None => {return Ok(())}
-							};
-
-
-							if let Some(member) = &cmd.member {
-								if let Some(user) = &member.user {
-									if cache.member(guild_id, user.id).is_none() {
-										http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-											.create_followup(
-												&cmd.token,
-											)
-											.content("You're not cached. Send a message somewhere and press me again.").unwrap()
-											.flags(MessageFlags::EPHEMERAL)
-											.exec()
-											.await.unwrap();
-		
-										return Ok(());							
-									}
-		
-									let member = cache.member(guild_id, user.id).unwrap();
-									for role in &config::CONFIG.banned_roles {
-										if member.roles().contains(&Id::<RoleMarker>::new(*role)) {
-											info!("Banned user tried running getting a role");
-											return Ok(());
-										}
-									}
-			
-									http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-										.create_response(
-											cmd.id,
-											&cmd.token,
-											&InteractionResponse { 
-												kind: InteractionResponseType::Modal,
-												data: Some(InteractionResponseData {
-										            allowed_mentions: None,
-										            attachments: None,
-										            choices: None,
-										            components: Some(vec![
-														Component::ActionRow(ActionRow {
-															components: vec![
-																Component::TextInput(TextInput { 
-																	custom_id: String::from("code-to-run"), 
-																	label: String::from("Code to run"), 
-																	max_length: None, 
-																	min_length: None, 
-																	placeholder: None, 
-																	required: Some(true), 
-																	style: TextInputStyle::Paragraph, 
-																	value: None
-																})
-															]
-														})
-													]),
-										            content: None,
-										            custom_id: Some(String::from("run-my-rust")),
-										            embeds: None,
-										            flags: None,
-										            title: Some(String::from("Rust Runner 9000")),
-										            tts: Some(false),
-										        })			
-											}
-										)
-										.exec()
-										.await.unwrap();							
-									}
-									
-							}
-						}
-						_ => {}
-					}
-				}
-			    twilight_model::application::interaction::Interaction::MessageComponent(msgcmp) => {
-					http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-						.create_response(
-							msgcmp.id, 
-							&msgcmp.token,
-							&InteractionResponse {
-								kind: InteractionResponseType::DeferredUpdateMessage,
-								data: None,
-				
-					        }
-						)
-						.exec()
-						.await.unwrap();
-
-					let guild_id = match interaction.guild_id() {
-				        Some(g) =>g,
-				        None => {return Ok(())}
-				    };
-					
-					if let Some(member) = &msgcmp.member {
-						if let Some(user) = &member.user {
-							if cache.member(guild_id, user.id).is_none() {
-								http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-									.create_followup(
-										&msgcmp.token,
-									)
-									.content("You're not cached. Send a message somewhere and press me again.").unwrap()
-									.flags(MessageFlags::EPHEMERAL)
-									.exec()
-									.await.unwrap();
-
-								return Ok(());							
-							}
-
-							let member = cache.member(guild_id, user.id).unwrap();
-	
-							for role in &config::CONFIG.banned_roles {
-								if member.roles().contains(&Id::<RoleMarker>::new(*role)) {
-									info!("Banned user tried running getting a role");
-									return Ok(());
-								}
-							}
-	
-							let role_id = match msgcmp.data.custom_id.parse::<u64>() {
-								Ok(id) => Id::<RoleMarker>::new(id),
-								Err(_) => {
-									http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-										.create_followup(
-											&msgcmp.token,
-										)
-										.content("Failed to get the role id. Sorry.").unwrap()
-										.flags(MessageFlags::EPHEMERAL)
-										.exec()
-										.await.unwrap();
-
-									return Ok(())
-								}
-							};
-	
-							let mut roles = member.roles().to_vec().clone();
-	
-							let message = if roles.contains(&role_id) {							
-								let new_roles = roles.iter().filter(|e| *e != &role_id).copied().collect::<Vec<Id<RoleMarker>>>();
-								http.update_guild_member(guild_id, user.id)
-									.roles(&new_roles).exec().await.unwrap();
-									format!("<:ferrischeck:957417376314429490> removed <@&{}>", role_id.get())
-							} else {
-								roles.push(role_id);
-								http.update_guild_member(guild_id, user.id)
-									.roles(&roles).exec().await.unwrap();
-								format!("<:ferrischeck:957417376314429490> added <@&{}>", role_id.get())
-							};
-							
-							http.interaction(Id::<ApplicationMarker>::new(config::CONFIG.bot_id))
-								.create_followup(
-									&msgcmp.token,
-								)
-								.content(&message).unwrap()
-								.flags(MessageFlags::EPHEMERAL)
-								.exec()
-								.await.unwrap();
-						}
-					}
-				},
-				_ => {}
-			}
+			registry.handle(ctx, &interaction.0).await?;
 		}
-        Event::MessageCreate(message) => {
-            if config::CONFIG.channels.contains(&message.channel_id.get()) {
-                if let Some(member) = &message.member {
-                    for role in &config::CONFIG.banned_roles {
-                        if member.roles.contains(&Id::<RoleMarker>::new(*role)) {
-                            info!("Banned user ({:?}) tried running rust in guild: {:?}, channel: {:?}", 
-								message.author.id,
-								message.guild_id,
-								message.channel_id
-							);
-                            return Ok(());
-                        }
-                    }
-                }
-
-                let loading = RequestReactionType::Unicode { name: "🌀" };
-                let failed = RequestReactionType::Unicode { name: "❌" };
-                let success = RequestReactionType::Unicode { name: "✅" };
-
-                let mut message_content = message.content.split("```").peekable();
-                if message_content.peek().is_some() && message_content.next().unwrap().is_empty() {
-                    if let Some(content) = message_content.next() {
-                        let mut splitted_newlines = content.split('\n').peekable();
-                        // If the message code black isn't `rs` or `rust` then just ignore it.
-                        if splitted_newlines.peek().is_some()
-                            && splitted_newlines.next().unwrap()  == "rs"
-                            || splitted_newlines.next().unwrap() == "rust"
-                        {
-                            http.create_reaction(message.channel_id, message.id, &loading)
-                                .exec()
-                                .await?;
-
-                            let playground = play::Playground {
-                                channel: "stable".to_string(),
-                                mode: "debug".to_string(),
-                                edition: "2021".to_string(),
-                                backtrace: false,
-                                tests: false,
-                                crate_type: "bin".to_string(),
-                                code: if content.contains("fn main()") {
-                                    splitted_newlines.collect::<Vec<&str>>().join("\n")
-                                } else {
-                                    format!(
-                                        "fn main() {{ println!(\"{{:?}}\", {{ {} }} ) }}",
-                                        splitted_newlines.collect::<Vec<&str>>().join("\n")
-                                    )
-                                },
-                            };
-
-                            let request = Request::builder()
-                                .uri("https://play.rust-lang.org/execute")
-                                .method("POST")
-                                .header("User-Agent", "RunMyRust/1.0")
-                                .header("Content-Type", "application/json")
-                                .body(Body::from(serde_json::to_vec(&playground)?))?;
-
-                            let response = match http2.request(request).await {
-                                Ok(r) => r,
-                                Err(_) => {
-                                    http.create_reaction(message.channel_id, message.id, &failed)
-                                        .exec()
-                                        .await?;
-                                    return Ok(());
-                                }
-                            };
-
-                            let body = hyper::body::aggregate(response).await?;
-                            let response: play::PlaygroundResult =
-                                serde_json::from_reader(body.reader())?;
-                            println!("{:#?}", response);
-                            // if response.success == false {
-                            http.create_reaction(message.channel_id, message.id, &success)
-                                .exec()
-                                .await?;
-
-                            let new_thread_channel = http
-                                .create_thread_from_message(
-                                    message.channel_id,
-                                    message.id,
-                                    &format!(
-                                        "{}-{}",
-                                        message.author.id,
-                                        message.id
-                                    ),
-                                )?
-                                .exec()
-                                .await?
-                                .model()
-                                .await?;
-
-                            let message = http
-                                .create_message(new_thread_channel.id)
-                                .content(&format!(
-                                    "Result: {}\nStds:\n**Out:** {}\n**Err:** ```{}```",
-                                    response.success,
-                                    response.stdout,
-                                    response.stderr.replace('`', "\"")
-                                ))?
-                                .exec()
-                                .await;
-
-                            match message {
-                                Ok(_) => info!("Successfully created a thread."),
-                                Err(_) => error!("Failed to created a thread."),
-                            }
-
-                            println!("{:?}", playground);
-                            //}
-
-                            return Ok(());
-                        }
-
-                        http.create_reaction(message.channel_id, message.id, &failed)
-                            .exec()
-                            .await?;
-                    }
-                }
+        Event::MessageCreate(message) if settings::is_run_channel(message.guild_id, message.channel_id) => {
+            let guild = match message.guild_id {
+                Some(guild) => guild,
+                None => return Ok(()),
+            };
+            let roles = message.member.as_ref().map(|member| member.roles.clone()).unwrap_or_default();
+
+            run_in_thread(http, backend, &message, guild, &roles).await?;
+        }
+        Event::MessageUpdate(update) if settings::is_run_channel(update.guild_id, update.channel_id) => {
+            // Discord sends updates for embeds showing up too, those don't have any content.
+            let content = match &update.content {
+                Some(content) => content,
+                None => return Ok(()),
+            };
+
+            if update.author.as_ref().is_some_and(|author| author.bot) {
+                return Ok(());
+            }
+
+            let previous = replies::get(update.id);
+            let runnable = !code::blocks(content).is_empty()
+                || update.attachments.as_deref().is_some_and(|attachments| attachment::runnable(attachments) > 0);
+            match &previous {
+                Some(previous) if previous.source == replies::source_hash(content) => return Ok(()),
+                None if !runnable => return Ok(()),
+                _ => {}
+            }
+
+            let message = http.message(update.channel_id, update.id)
+                .exec()
+                .await?
+                .model()
+                .await?;
+            // Updates don't always say who wrote the message, the message itself does.
+            if message.author.bot {
+                return Ok(());
+            }
+
+            // Messages from the API don't say which guild they're in.
+            let guild = match update.guild_id {
+                Some(guild) => guild,
+                None => return Ok(()),
+            };
+            let roles = cache.member(guild, message.author.id)
+                .map(|member| member.roles().to_vec())
+                .unwrap_or_default();
+
+            run_in_thread(http, backend, &message, guild, &roles).await?;
+        }
+        Event::MessageDelete(delete) if settings::is_run_channel(delete.guild_id, delete.channel_id) => {
+            replies::clean_up(http, delete.channel_id, delete.id).await;
+        }
+        Event::MessageDeleteBulk(delete) if settings::is_run_channel(delete.guild_id, delete.channel_id) => {
+            for id in delete.ids {
+                replies::clean_up(http, delete.channel_id, id).await;
             }
         }
         _ => {}
@@ -0,0 +1,62 @@
+use std::{
+	convert::Infallible,
+	net::SocketAddr,
+	path::{Path, PathBuf},
+};
+
+use hyper::{
+	service::{make_service_fn, service_fn},
+	Body, Request, Response, Server, StatusCode,
+};
+use tracing::{error, info};
+
+use crate::storage;
+
+/// Saves `code` as a paste, giving back its id. The id comes from the code, so
+/// running the same thing twice doesn't make two pastes.
+pub async fn save(dir: &Path, code: &str) -> std::io::Result<String> {
+	let id = storage::code_hash(code);
+
+	tokio::fs::create_dir_all(dir).await?;
+	tokio::fs::write(dir.join(format!("{}.rs", id)), code).await?;
+	Ok(id)
+}
+
+/// Serves pastes saved with `save` as plain text at `/<id>`, for when there's
+/// no public playground to make gists on.
+pub async fn serve(addr: SocketAddr, dir: PathBuf) {
+	let make_service = make_service_fn(move |_| {
+		let dir = dir.clone();
+		async move {
+			Ok::<_, Infallible>(service_fn(move |request| paste(dir.clone(), request)))
+		}
+	});
+
+	info!("Serving pastes on {}", addr);
+	if let Err(why) = Server::bind(&addr).serve(make_service).await {
+		error!("The paste server stopped: {}", why);
+	}
+}
+
+async fn paste(dir: PathBuf, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+	let id = request.uri().path().trim_start_matches('/');
+
+	// Ids are only ever hex, anything else could be trying to get out of `dir`.
+	if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+		return Ok(not_found());
+	}
+
+	match tokio::fs::read(dir.join(format!("{}.rs", id))).await {
+		Ok(code) => Ok(Response::builder()
+			.header("Content-Type", "text/plain; charset=utf-8")
+			.body(Body::from(code))
+			.unwrap_or_else(|_| not_found())),
+		Err(_) => Ok(not_found()),
+	}
+}
+
+fn not_found() -> Response<Body> {
+	let mut response = Response::new(Body::from("No paste here."));
+	*response.status_mut() = StatusCode::NOT_FOUND;
+	response
+}
@@ -1,11 +1,12 @@
 use serde::{Deserialize, Serialize};
 
+use crate::code::{self, Channel, Mode, RunOptions, Target, Tool};
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Playground {
     pub channel: String,
     pub mode: String,
     pub edition: String,
-    // Both of these values will always be `false`. just fyi.
     pub backtrace: bool,
     pub tests: bool,
     #[serde(rename = "crateType")]
@@ -19,3 +20,126 @@ pub struct PlaygroundResult {
     pub stdout: String,
     pub stderr: String,
 }
+
+/// What clippy, rustfmt, miri and macro expansion give back. Only rustfmt
+/// fills in `code`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ToolResult {
+    pub success: bool,
+    #[serde(default)]
+    pub code: String,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// A request to the playground's `/compile`, which shows what code compiles to
+/// instead of running it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompileRequest {
+    pub target: String,
+    #[serde(rename = "assemblyFlavor")]
+    pub assembly_flavor: String,
+    #[serde(rename = "demangleAssembly")]
+    pub demangle_assembly: String,
+    #[serde(rename = "processAssembly")]
+    pub process_assembly: String,
+    pub channel: String,
+    pub mode: String,
+    pub edition: String,
+    pub backtrace: bool,
+    pub tests: bool,
+    #[serde(rename = "crateType")]
+    pub crate_type: String,
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompileResult {
+    pub success: bool,
+    pub code: String,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CompileRequest {
+    /// Builds the request from the same thing a run would use, so code gets
+    /// wrapped (or not) the same way.
+    pub fn new(target: Target, playground: Playground) -> Self {
+        CompileRequest {
+            target: target.to_string(),
+            assembly_flavor: "intel".to_string(),
+            demangle_assembly: "demangle".to_string(),
+            process_assembly: "filter".to_string(),
+            // HIR is only ever shown with `-Zunpretty`, which is nightly only.
+            channel: if target == Target::Hir { Channel::Nightly.to_string() } else { playground.channel },
+            mode: playground.mode,
+            edition: playground.edition,
+            backtrace: false,
+            tests: false,
+            crate_type: playground.crate_type,
+            code: playground.code,
+        }
+    }
+}
+
+impl Playground {
+    /// Builds a request for `code`.
+    ///
+    /// Code with `#[test]`s (or run in test mode) gets its tests run. Code that's
+    /// nothing but items is compiled as a library, and anything else without a
+    /// `main` is treated as an expression and wrapped in one that prints it.
+    pub fn new(code: &str, options: &RunOptions) -> Self {
+        let tests = options.mode == Mode::Test || code.contains("#[test]");
+        let has_main = code.contains("fn main()");
+        let library = !has_main && (tests || code::only_items(code));
+
+        Playground {
+            channel: options.channel.to_string(),
+            mode: match options.mode {
+                Mode::Release => "release".to_string(),
+                Mode::Debug | Mode::Test => "debug".to_string(),
+            },
+            edition: options.edition.to_string(),
+            backtrace: options.backtrace,
+            tests,
+            crate_type: if library { "lib" } else { "bin" }.to_string(),
+            // Formatting the `main` we wrap things in would just confuse people.
+            code: if has_main || library || options.tool == Some(Tool::Rustfmt) {
+                code.to_string()
+            } else {
+                format!("fn main() {{ println!(\"{{:?}}\", {{ {} }} ) }}", code)
+            },
+        }
+    }
+}
+
+/// A file from an uploaded crate, `path` being relative to its `Cargo.toml`.
+#[derive(Debug)]
+pub struct CrateFile {
+    pub path: String,
+    pub contents: Vec<u8>,
+}
+
+/// A whole crate to build and run with cargo, rather than a single file.
+#[derive(Debug)]
+pub struct Crate {
+    pub channel: String,
+    pub release: bool,
+    pub tests: bool,
+    pub backtrace: bool,
+    pub files: Vec<CrateFile>,
+}
+
+impl Crate {
+    /// The edition comes from the crate's own `Cargo.toml`, so it's the only
+    /// option that isn't used.
+    pub fn new(files: Vec<CrateFile>, options: &RunOptions) -> Self {
+        Crate {
+            channel: options.channel.to_string(),
+            release: options.mode == Mode::Release,
+            tests: options.mode == Mode::Test,
+            backtrace: options.backtrace,
+            files,
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+	collections::hash_map::RandomState,
+	fmt,
+	hash::{BuildHasher, Hasher},
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use hyper::body::Buf;
+use hyper::{client::HttpConnector, Body, Client as HyperClient, Request, StatusCode};
+use hyper_tls::HttpsConnector;
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::warn;
+
+use crate::config::RemoteBackendConfig;
+
+/// How long to wait before the first retry, doubled for each one after.
+const RETRY_BASE: Duration = Duration::from_millis(500);
+
+/// Talks to the playground's API. Requests time out, ones the playground
+/// fails (5xx, timeouts, not answering) are retried a few times, and after
+/// enough of those in a row it's assumed to be down and requests fail straight
+/// away until `cooldown` has passed. Only a result coming back counts as it
+/// being up again.
+pub struct PlaygroundClient {
+	client: HyperClient<HttpsConnector<HttpConnector>>,
+	url: String,
+	timeout: Duration,
+	retries: u32,
+	failure_threshold: u32,
+	cooldown: Duration,
+	breaker: Mutex<Breaker>,
+}
+
+#[derive(Default)]
+struct Breaker {
+	/// Requests the playground failed in a row.
+	failures: u32,
+	/// While set, it's down and nothing gets sent until then.
+	open_until: Option<Instant>,
+}
+
+#[derive(Debug)]
+pub enum PlaygroundError {
+	/// Too many requests failed recently, so this one wasn't sent.
+	Down,
+	Timeout,
+	Status(StatusCode),
+	/// The playground answered, but not with what was asked for (like an error page).
+	Invalid(serde_json::Error),
+	Request(hyper::Error),
+}
+
+impl PlaygroundError {
+	/// Whether it's the playground's fault, so counting towards it being down.
+	fn is_outage(&self) -> bool {
+		match self {
+			PlaygroundError::Status(status) => status.is_server_error(),
+			PlaygroundError::Down | PlaygroundError::Invalid(_) => false,
+			PlaygroundError::Timeout | PlaygroundError::Request(_) => true,
+		}
+	}
+
+	/// Whether a request to `path` is worth sending again. Code that timed out
+	/// running would most likely just time out again, taking twice as long.
+	fn is_retryable(&self, path: &str) -> bool {
+		match self {
+			PlaygroundError::Timeout => !RUNS_CODE.contains(&path),
+			_ => self.is_outage(),
+		}
+	}
+}
+
+/// The endpoints that run the code they're given, rather than just compiling it.
+const RUNS_CODE: [&str; 2] = ["execute", "miri"];
+
+impl fmt::Display for PlaygroundError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			PlaygroundError::Down => write!(f, "🔥 The playground seems to be down right now, try again in a bit."),
+			PlaygroundError::Timeout => write!(f, "⏳ The playground took too long to answer, try again in a bit."),
+			PlaygroundError::Status(status) => write!(f, "The playground had a problem with that ({}).", status),
+			PlaygroundError::Invalid(_) => write!(f, "The playground sent back something that wasn't a result."),
+			PlaygroundError::Request(_) => write!(f, "Couldn't reach the playground, try again in a bit."),
+		}
+	}
+}
+
+impl std::error::Error for PlaygroundError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			PlaygroundError::Invalid(why) => Some(why),
+			PlaygroundError::Request(why) => Some(why),
+			_ => None,
+		}
+	}
+}
+
+impl PlaygroundClient {
+	pub fn new(config: &RemoteBackendConfig) -> Self {
+		Self {
+			client: HyperClient::builder().build::<_, Body>(HttpsConnector::new()),
+			url: config.url.trim_end_matches('/').to_string(),
+			timeout: Duration::from_secs(config.timeout_secs),
+			retries: config.retries,
+			failure_threshold: config.failure_threshold.max(1),
+			cooldown: Duration::from_secs(config.cooldown_secs),
+			breaker: Mutex::new(Breaker::default()),
+		}
+	}
+
+	pub fn url(&self) -> &str {
+		&self.url
+	}
+
+	/// POSTs `body` as JSON to `path`, giving back the JSON that comes back.
+	pub async fn post<T: Serialize + Sync, R: DeserializeOwned>(&self, path: &str, body: &T) -> Result<R, PlaygroundError> {
+		if self.is_down() {
+			return Err(PlaygroundError::Down);
+		}
+
+		let body = serde_json::to_vec(body).map_err(PlaygroundError::Invalid)?;
+		let mut attempt = 0;
+		loop {
+			let result = self.attempt(path, body.clone()).await;
+			match &result {
+				Ok(_) => {
+					self.succeeded();
+					return result;
+				}
+				Err(why) if why.is_retryable(path) && attempt < self.retries => {
+					let wait = jitter(RETRY_BASE * 2u32.pow(attempt));
+					warn!("Playground request to {} failed ({:?}), retrying in {:?}", path, why, wait);
+					tokio::time::sleep(wait).await;
+					attempt += 1;
+				}
+				Err(why) => {
+					if why.is_outage() {
+						self.failed();
+					}
+					return result;
+				}
+			}
+		}
+	}
+
+	async fn attempt<R: DeserializeOwned>(&self, path: &str, body: Vec<u8>) -> Result<R, PlaygroundError> {
+		let request = Request::builder()
+			.uri(format!("{}/{}", self.url, path))
+			.method("POST")
+			.header("User-Agent", "RunMyRust/1.0")
+			.header("Content-Type", "application/json")
+			.body(Body::from(body))
+			.expect("playground requests are always valid");
+
+		let response = async {
+			let response = self.client.request(request).await.map_err(PlaygroundError::Request)?;
+			if !response.status().is_success() {
+				return Err(PlaygroundError::Status(response.status()));
+			}
+			hyper::body::aggregate(response).await.map_err(PlaygroundError::Request)
+		};
+
+		let body = tokio::time::timeout(self.timeout, response)
+			.await
+			.map_err(|_| PlaygroundError::Timeout)??;
+
+		serde_json::from_reader(body.reader()).map_err(PlaygroundError::Invalid)
+	}
+
+	/// Whether the breaker's open. Once the cooldown's over requests are let
+	/// through again, but another failure opens it straight back up.
+	fn is_down(&self) -> bool {
+		let breaker = self.breaker.lock().unwrap();
+		matches!(breaker.open_until, Some(until) if Instant::now() < until)
+	}
+
+	fn failed(&self) {
+		let mut breaker = self.breaker.lock().unwrap();
+		breaker.failures += 1;
+		if breaker.failures >= self.failure_threshold {
+			warn!("Playground failed {} requests in a row, not sending any for {:?}", breaker.failures, self.cooldown);
+			breaker.open_until = Some(Instant::now() + self.cooldown);
+		}
+	}
+
+	fn succeeded(&self) {
+		*self.breaker.lock().unwrap() = Breaker::default();
+	}
+}
+
+/// Somewhere between half and one and a half times `wait`, so retries from a
+/// lot of runs at once don't all land together.
+fn jitter(wait: Duration) -> Duration {
+	let random = RandomState::new().build_hasher().finish();
+	wait.mul_f64(0.5 + (random % 1000) as f64 / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn retries_outages() {
+		assert!(PlaygroundError::Status(StatusCode::BAD_GATEWAY).is_retryable("compile"));
+		assert!(!PlaygroundError::Status(StatusCode::BAD_REQUEST).is_retryable("compile"));
+		assert!(!PlaygroundError::Down.is_retryable("compile"));
+	}
+
+	#[test]
+	fn timeouts_running_code_are_not_retried() {
+		assert!(PlaygroundError::Timeout.is_retryable("compile"));
+		assert!(!PlaygroundError::Timeout.is_retryable("execute"));
+		assert!(!PlaygroundError::Timeout.is_retryable("miri"));
+		// It's still the playground being slow though.
+		assert!(PlaygroundError::Timeout.is_outage());
+	}
+}
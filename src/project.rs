@@ -0,0 +1,187 @@
+use async_trait::async_trait;
+use tracing::error;
+use twilight_model::{
+	application::{
+		command::{BaseCommandOptionData, ChoiceCommandOptionData, Command, CommandOption, CommandType},
+		component::{action_row::ActionRow, button::{Button, ButtonStyle}, Component},
+		interaction::{application_command::{CommandDataOption, CommandOptionValue}, ApplicationCommand},
+	},
+	id::{marker::ChannelMarker, Id},
+};
+use twilight_util::builder::{command::CommandBuilder, embed::{EmbedAuthorBuilder, EmbedBuilder, ImageSource}};
+
+use crate::audit;
+use crate::config;
+use crate::error::BotError;
+use crate::handler::{Context, Invoker, SlashCommand};
+use crate::storage;
+
+/// `/project`, posts someone's project in the projects channel with a thread to talk about it.
+pub struct ProjectCommand;
+
+#[async_trait]
+impl SlashCommand for ProjectCommand {
+	fn command(&self) -> Command {
+		CommandBuilder::new(
+			"project".into(),
+			"Show off a neat project of yours!".into(),
+			CommandType::ChatInput,
+		)
+		.option(
+			CommandOption::String(ChoiceCommandOptionData {
+				autocomplete: false,
+				choices: vec![],
+				description: String::from("Whats the name of your project?"),
+				name: String::from("name"),
+				required: true,
+			})
+		)
+		.option(
+			CommandOption::String(ChoiceCommandOptionData {
+				autocomplete: false,
+				choices: vec![],
+				description: String::from("Whats your project's description? Max of 1000 characters. Keep it short and simple."),
+				name: String::from("description"),
+				required: false,
+			})
+		)
+		.option(
+			CommandOption::Boolean(BaseCommandOptionData {
+				description: String::from("Is this project on crates.io? Make sure the name you set is the same on crates.io"),
+				name: String::from("crates-io"),
+				required: false,
+			})
+		)
+		.option(
+			CommandOption::String(ChoiceCommandOptionData {
+				autocomplete: false,
+				choices: vec![],
+				description: String::from("Does your project have a github repo?"),
+				name: String::from("github"),
+				required: false,
+			}))
+		.build()
+	}
+
+	async fn run(&self, ctx: &Context, cmd: &ApplicationCommand, invoker: &Invoker) -> Result<(), BotError> {
+		let channel_id = config::get().guild(invoker.guild)
+			.and_then(|guild| guild.projects_channel)
+			.ok_or_else(|| BotError::Config(String::from("there's no projects channel")))?;
+		let user = &invoker.user;
+
+		ctx.defer_ephemeral(cmd.id, &cmd.token).await?;
+
+		let name = match &cmd.data.options.iter().find(|e| e.name == "name" ).map(|e| &e.value) {
+			Some(CommandOptionValue::String(string)) => string,
+			_ => return Err(BotError::Validation(String::from("That name you provided was mangled by Discord?"))),
+		};
+
+		let github = cmd.data.options.iter().find(|e| e.name == "github" );
+		let description = cmd.data.options.iter().find(|e| e.name == "description" );
+		let crates_io = cmd.data.options.iter().find(|e| e.name == "crates-io" );
+
+		let mut comps = Vec::new();
+
+		if let Some(crates_io) = crates_io {
+			if let CommandOptionValue::Boolean(true) = crates_io.value {
+				comps.push(Component::Button(Button {
+					custom_id: None,
+					disabled: false,
+					emoji: None,
+					label: Some("view on crates.io".to_string()),
+					style: ButtonStyle::Link,
+					url: Some(format!("https://crates.io/crates/{}", name)),
+				}))
+			}
+		}
+
+		if let Some(github) = github {
+			if let CommandOptionValue::String(github) = &github.value {
+				comps.push(Component::Button(Button {
+					custom_id: None,
+					disabled: false,
+					emoji: None,
+					label: Some("view on github".to_string()),
+					style: ButtonStyle::Link,
+					url: Some(github.to_string()),
+				}))
+			}
+		}
+
+		let author = EmbedAuthorBuilder::new(format!("{}#{}", user.name, user.discriminator()));
+
+		let author = if let Some(avatar) = user.avatar {
+			match ImageSource::url(format!(
+				"https://cdn.discordapp.com/avatars/{}/{}.png",
+				user.id, avatar
+			)) {
+				Ok(icon_url) => {
+					author.icon_url(icon_url)
+				},
+				Err(_) => author
+			}
+		} else { author };
+
+		let embed = EmbedBuilder::new()
+			.author(author.build())
+			.title(name)
+			.color(11237454);
+
+		let embed = if let Some(description) = description {
+			match &description.value {
+				CommandOptionValue::String(description) => {
+					if description.len() > 1000 || description.is_empty() {
+						return Err(BotError::Validation(String::from("Sorry your description is either too large or too small. Remmber it cannot be above 1000 characters and must be above 1 character.")));
+					}
+
+					embed.description(description)
+				},
+				_ => embed
+			}
+		} else {
+			embed
+		};
+		let embed = embed.validate()?;
+		let embed = vec![embed.build()];
+
+		let mut posted = None;
+		if let Ok(message) = ctx.http.create_message(Id::<ChannelMarker>::new(channel_id))
+			.embeds(&embed)?
+			.content("A new project has been discovered!")?
+			.components(&[Component::ActionRow(ActionRow {
+				components: comps
+			})])?
+			.exec().await {
+				if let Ok(m) = message.model().await {
+					let _ = ctx.http.create_thread_from_message(m.channel_id, m.id, name)?.exec().await;
+					posted = Some(m.id);
+				}
+			}
+
+		let text = |option: Option<&CommandDataOption>| match option.map(|o| &o.value) {
+			Some(CommandOptionValue::String(value)) => Some(value.clone()),
+			_ => None,
+		};
+		let project = storage::Project {
+			user: user.id,
+			guild: invoker.guild,
+			name: name.clone(),
+			description: text(description),
+			github: text(github),
+			crates_io: matches!(crates_io.map(|o| &o.value), Some(CommandOptionValue::Boolean(true))),
+			message: posted,
+		};
+		if let Err(why) = storage::get().record_project(&project) {
+			error!("Failed to record a project: {}", why);
+		}
+
+		ctx.ephemeral(&cmd.token, "👍").await?;
+
+		audit::log(&ctx.http, invoker.guild, audit::Entry::Project {
+			user: user.id,
+			name: name.clone(),
+			posted: posted.map(|message| (Id::new(channel_id), message)),
+		}).await;
+		Ok(())
+	}
+}
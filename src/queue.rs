@@ -0,0 +1,255 @@
+use std::{
+	collections::{HashMap, VecDeque},
+	sync::Mutex,
+	time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use tokio::sync::oneshot;
+use twilight_http::Client;
+use twilight_model::id::{marker::{ApplicationMarker, UserMarker}, Id};
+
+use crate::config;
+use crate::error::BotError;
+
+/// How often someone waiting gets told where they are in line.
+const POSITION_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Runs waiting for a turn. Only `max_concurrent_runs` happen at once, and
+/// turns go round the users waiting one at a time, so someone with a lot of
+/// runs queued up can't hold everyone else up.
+static QUEUE: Lazy<Mutex<Queue>> = Lazy::new(Default::default);
+
+#[derive(Default)]
+struct Queue {
+	running: usize,
+	/// Users with runs waiting, in the order they get their next turn.
+	users: VecDeque<u64>,
+	waiting: HashMap<u64, VecDeque<Waiter>>,
+	next_id: u64,
+}
+
+struct Waiter {
+	id: u64,
+	turn: oneshot::Sender<()>,
+}
+
+impl Queue {
+	/// Puts a run by `user` in line, giving back its id and what its turn comes through.
+	fn push(&mut self, user: u64) -> (u64, oneshot::Receiver<()>) {
+		let id = self.next_id;
+		self.next_id += 1;
+
+		let (sender, receiver) = oneshot::channel();
+		if !self.users.contains(&user) {
+			self.users.push_back(user);
+		}
+		self.waiting.entry(user).or_default().push_back(Waiter { id, turn: sender });
+		(id, receiver)
+	}
+
+	/// Where a waiter is in line, going round the users the same way turns do.
+	fn position(&self, id: u64) -> Option<usize> {
+		let longest = self.waiting.values().map(VecDeque::len).max().unwrap_or(0);
+		let mut order = (0..longest).flat_map(|round| {
+			self.users.iter().filter_map(move |user| self.waiting.get(user).and_then(|w| w.get(round)))
+		});
+
+		order.position(|waiter| waiter.id == id).map(|position| position + 1)
+	}
+
+	/// Gives free slots (up to `max` running at once) to whoever's next.
+	fn dispatch(&mut self, max: usize) {
+		while self.running < max {
+			let user = match self.users.pop_front() {
+				Some(user) => user,
+				None => return,
+			};
+
+			let waiters = self.waiting.entry(user).or_default();
+			let waiter = waiters.pop_front();
+			if waiters.is_empty() {
+				self.waiting.remove(&user);
+			} else {
+				self.users.push_back(user);
+			}
+
+			// A waiter that's gone (its handler gave up) doesn't need its turn.
+			if let Some(waiter) = waiter {
+				if waiter.turn.send(()).is_ok() {
+					self.running += 1;
+				}
+			}
+		}
+	}
+}
+
+/// A turn to run something. The next one is handed out when it's dropped.
+pub struct Permit(());
+
+impl Drop for Permit {
+	fn drop(&mut self) {
+		let mut queue = QUEUE.lock().unwrap();
+		queue.running -= 1;
+		queue.dispatch(config::get().max_concurrent_runs);
+	}
+}
+
+/// A place in line.
+pub struct Ticket {
+	id: u64,
+	turn: Option<oneshot::Receiver<()>>,
+	told: Option<usize>,
+}
+
+pub enum Turn {
+	Ready(Permit),
+	/// Still waiting, this far back in line.
+	Queued(usize),
+}
+
+/// Gets in line for a turn to run something.
+pub fn join(user: Id<UserMarker>) -> Ticket {
+	let mut queue = QUEUE.lock().unwrap();
+	let (id, receiver) = queue.push(user.get());
+	queue.dispatch(config::get().max_concurrent_runs);
+
+	Ticket { id, turn: Some(receiver), told: None }
+}
+
+impl Ticket {
+	/// Waits until it's this ticket's turn, or its place in line has changed
+	/// since it was last asked.
+	pub async fn turn(&mut self) -> Turn {
+		loop {
+			let turn = match self.turn.as_mut() {
+				Some(turn) => turn,
+				None => unreachable!("a ticket's turn was taken twice"),
+			};
+
+			if turn.try_recv().is_ok() {
+				self.turn = None;
+				return Turn::Ready(Permit(()));
+			}
+
+			let position = QUEUE.lock().unwrap().position(self.id);
+			if let Some(position) = position.filter(|p| self.told != Some(*p)) {
+				self.told = Some(position);
+				return Turn::Queued(position);
+			}
+
+			if tokio::time::timeout(POSITION_INTERVAL, &mut *turn).await.is_ok() {
+				self.turn = None;
+				return Turn::Ready(Permit(()));
+			}
+		}
+	}
+}
+
+impl Drop for Ticket {
+	fn drop(&mut self) {
+		// A turn handed out that nobody took still has to be given back.
+		if let Some(mut turn) = self.turn.take() {
+			turn.close();
+			if turn.try_recv().is_ok() {
+				drop(Permit(()));
+			}
+		}
+	}
+}
+
+/// Waits for a turn for an interaction that's been answered (or deferred),
+/// showing where it is in line in a followup until then.
+pub async fn wait_for_interaction(http: &Client, token: &str, user: Id<UserMarker>) -> Result<Permit, BotError> {
+	let interaction = http.interaction(Id::<ApplicationMarker>::new(config::get().bot_id));
+	let mut ticket = join(user);
+	let mut note = None;
+
+	loop {
+		match ticket.turn().await {
+			Turn::Queued(position) => {
+				let content = format!("🌀 Queued #{}, hang on...", position);
+				note = Some(match note {
+					Some(note) => {
+						interaction.update_followup(token, note).content(Some(&content))?.exec().await?;
+						note
+					}
+					None => interaction.create_followup(token)
+						.content(&content)?
+						.exec()
+						.await?
+						.model()
+						.await?
+						.id,
+				});
+			}
+			Turn::Ready(permit) => {
+				// Results get sent as followups, so the note isn't needed anymore.
+				if let Some(note) = note {
+					interaction.delete_followup(token, note).exec().await?;
+				}
+				return Ok(permit);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Whose runs have been given a turn so far, in the order they got it.
+	fn turns(waiting: &mut [(u64, oneshot::Receiver<()>)]) -> Vec<u64> {
+		waiting.iter_mut().filter_map(|(user, turn)| turn.try_recv().ok().map(|_| *user)).collect()
+	}
+
+	#[test]
+	fn turns_go_round_the_users() {
+		let mut queue = Queue::default();
+		let mut waiting = [1, 1, 1, 2, 3].map(|user| (user, queue.push(user).1));
+
+		queue.dispatch(1);
+		assert_eq!(turns(&mut waiting), [1]);
+
+		let mut order = Vec::new();
+		for _ in 0..4 {
+			queue.running -= 1;
+			queue.dispatch(1);
+			order.extend(turns(&mut waiting));
+		}
+		// User 1 queued three runs first, but 2 and 3 don't wait behind all of them.
+		assert_eq!(order, [2, 3, 1, 1]);
+		assert!(queue.users.is_empty() && queue.waiting.is_empty());
+	}
+
+	#[test]
+	fn only_max_run_at_once() {
+		let mut queue = Queue::default();
+		let mut waiting = [1, 2, 3].map(|user| (user, queue.push(user).1));
+
+		queue.dispatch(2);
+		assert_eq!(turns(&mut waiting), [1, 2]);
+		assert_eq!(queue.running, 2);
+	}
+
+	#[test]
+	fn positions_follow_the_turn_order() {
+		let mut queue = Queue::default();
+		let ids = [1, 1, 2].map(|user| queue.push(user).0);
+
+		assert_eq!(ids.map(|id| queue.position(id)), [Some(1), Some(3), Some(2)]);
+		assert_eq!(queue.position(99), None);
+	}
+
+	#[test]
+	fn gone_waiters_are_skipped() {
+		let mut queue = Queue::default();
+		let (_, gone) = queue.push(1);
+		let (_, mut next) = queue.push(2);
+		drop(gone);
+
+		queue.dispatch(1);
+		assert!(next.try_recv().is_ok());
+		assert_eq!(queue.running, 1);
+	}
+}
@@ -0,0 +1,308 @@
+use crate::code::Tool;
+use crate::play::{Crate, Playground, PlaygroundResult, ToolResult};
+
+/// Turns a finished run into the message the bot replies with.
+pub fn result(playground: &Playground, result: &PlaygroundResult) -> String {
+	let err = stderr(playground.backtrace, &result.stderr);
+
+	if playground.tests {
+		if let Some(summary) = test_summary(&result.stdout) {
+			return format!("Result: {}\n{}\n{}", result.success, summary, err);
+		}
+	} else if playground.crate_type == "lib" {
+		return format!(
+			"Result: {}\nCompiled as a library, there's no `main` to run.\n{}",
+			result.success,
+			err
+		);
+	}
+
+	format!(
+		"Result: {}\nStds:\n**Out:** {}\n{}",
+		result.success,
+		result.stdout,
+		err
+	)
+}
+
+/// Turns a finished crate run into the message the bot replies with. Cargo
+/// runs quietly, so whatever's in stderr is the compiler's or the program's.
+pub fn crate_result(krate: &Crate, result: &PlaygroundResult) -> String {
+	let err = stderr(krate.backtrace, &result.stderr);
+
+	match krate.tests.then(|| test_summary(&result.stdout)).flatten() {
+		Some(summary) => format!("Result: {}\n{}\n{}", result.success, summary, err),
+		None => format!("Result: {}\nStds:\n**Out:** {}\n{}", result.success, result.stdout, err),
+	}
+}
+
+/// Turns what a tool gave back into the message the bot replies with.
+pub fn tool(tool: Tool, result: &ToolResult) -> String {
+	match tool {
+		Tool::Clippy => clippy(result),
+		Tool::Rustfmt if result.success => format!("**Formatted:** ```rust\n{}```", escape(&result.code)),
+		Tool::Miri => miri(result),
+		Tool::Expand if result.success => format!("**Expanded:** ```rust\n{}```", escape(&result.stdout)),
+		Tool::Rustfmt | Tool::Expand => format!("Result: false\n**Err:** ```{}```", escape(&result.stderr)),
+	}
+}
+
+/// Lists the lints clippy found, one line each, rather than its whole (long) output.
+fn clippy(result: &ToolResult) -> String {
+	let mut lints = Vec::new();
+	let mut lines = result.stderr.lines().peekable();
+
+	while let Some(line) = lines.next() {
+		let (icon, message) = match (line.strip_prefix("warning: "), line.strip_prefix("error: ")) {
+			(Some(message), _) => ("⚠️", message),
+			(_, Some(message)) => ("❌", message),
+			_ => continue,
+		};
+
+		// Summaries like `warning: 2 warnings emitted` don't point anywhere.
+		let location = match lines.peek().and_then(|l| l.trim().strip_prefix("--> ")) {
+			Some(location) => location.to_string(),
+			None => continue,
+		};
+
+		// The lint's name is at the end of the link to its docs.
+		let name = lines.clone()
+			.take_while(|l| !l.is_empty())
+			.find_map(|l| l.split_once("index.html#").map(|(_, name)| name.trim()))
+			.map(|name| format!(" `clippy::{}`", name))
+			.unwrap_or_default();
+
+		lints.push(format!("{}{} at `{}`: {}", icon, name, location, message.replace('`', "'")));
+	}
+
+	if lints.is_empty() && result.success {
+		return String::from("**Clippy:** ✅ nothing to complain about.");
+	}
+	if lints.is_empty() {
+		return format!("**Clippy:** ```{}```", escape(&result.stderr));
+	}
+
+	format!("**Clippy:** {} found\n{}", lints.len(), lints.join("\n"))
+}
+
+/// Miri's report is the `Undefined Behavior` error and the notes after it, the
+/// rest is cargo talking.
+fn miri(result: &ToolResult) -> String {
+	let report = result.stderr.find("error: Undefined Behavior").map(|start| {
+		let report = &result.stderr[start..];
+		let end = report.find("\nnote: some details are omitted").or_else(|| report.find("\nerror: aborting")).unwrap_or(report.len());
+		report[..end].trim()
+	});
+
+	match report {
+		Some(report) => format!("**Miri found undefined behavior:** ```{}```\n**Out:** {}", escape(report), result.stdout),
+		None if result.success => format!("**Miri:** ✅ no undefined behavior found.\n**Out:** {}", result.stdout),
+		None => format!("**Miri:** ```{}```\n**Out:** {}", escape(&result.stderr), result.stdout),
+	}
+}
+
+/// The `**Err:**` part of a reply. With backtraces on, a panic gets split out of
+/// it and shown on its own, with the backtrace hidden behind a spoiler.
+fn stderr(backtrace: bool, stderr: &str) -> String {
+	let (rest, panic) = match backtrace.then(|| Panic::parse(stderr)).flatten() {
+		Some(panic) => (&stderr[..panic.start], Some(panic)),
+		None => (stderr, None),
+	};
+
+	let mut err = format!("**Err:** ```{}```", escape(rest));
+
+	if let Some(panic) = panic {
+		err.push_str(&format!("\n**Panicked at** `{}`**:** ```{}```", panic.location, escape(&panic.message)));
+		if !panic.frames.is_empty() {
+			err.push_str(&format!("\n**Backtrace:** ||```{}```||", escape(&panic.frames.join("\n"))));
+		}
+	}
+	err
+}
+
+/// A panic found in a program's stderr.
+struct Panic {
+	/// Where in stderr the panic starts. Anything before it is from the compiler.
+	start: usize,
+	location: String,
+	message: String,
+	/// Backtrace frames from the user's code, std and the runtime left out.
+	frames: Vec<String>,
+}
+
+impl Panic {
+	fn parse(stderr: &str) -> Option<Panic> {
+		let start = stderr.find("thread '")?;
+		let panic = &stderr[start..];
+		let (_, after) = panic.split_once(" panicked at ")?;
+
+		// Newer rusts put the message on its own line(s) after the location,
+		// older ones do `panicked at 'message', src/main.rs:2:5`.
+		let (location, message) = match after.strip_prefix('\'') {
+			Some(old) => {
+				let (message, location) = old.split_once("', ")?;
+				(location.lines().next()?, message.to_string())
+			}
+			None => {
+				let (location, message) = after.split_once('\n')?;
+				let message = message.lines()
+					.take_while(|l| !l.starts_with("note: ") && !l.starts_with("stack backtrace:"))
+					.collect::<Vec<_>>()
+					.join("\n");
+				(location.trim_end_matches(':'), message)
+			}
+		};
+
+		Some(Panic {
+			start,
+			location: location.to_string(),
+			message,
+			frames: panic.split_once("stack backtrace:\n").map(|(_, bt)| user_frames(bt)).unwrap_or_default(),
+		})
+	}
+}
+
+/// Frames from std, core, the panic machinery and test harness aren't interesting to anyone.
+fn user_frames(backtrace: &str) -> Vec<String> {
+	const HIDDEN: &[&str] = &[
+		"std::", "core::", "alloc::", "test::", "__rustc::", "rust_begin_unwind", "rust_panic",
+		"__rust_", "<fn(", "<alloc::", "<core::", "<std::", "__libc_", "_start", "main",
+	];
+
+	let mut frames = Vec::new();
+	let mut lines = backtrace.lines().peekable();
+
+	while let Some(line) = lines.next() {
+		let (number, symbol) = match line.trim().split_once(": ") {
+			Some((number, symbol)) if number.chars().all(|c| c.is_ascii_digit()) => (number, symbol),
+			_ => continue,
+		};
+
+		let location = match lines.peek().and_then(|l| l.trim().strip_prefix("at ")) {
+			Some(location) => {
+				let location = location.to_string();
+				lines.next();
+				Some(location)
+			}
+			None => None,
+		};
+
+		if HIDDEN.iter().any(|hidden| symbol.starts_with(hidden)) {
+			continue;
+		}
+
+		// Frames that point into the snippet (or crate) itself get an arrow so they
+		// stand out. Anything from std or a dependency lives somewhere else.
+		let marker = match &location {
+			Some(location) if location.contains(".rs:") && !location.starts_with("/rustc/") && !location.contains("/.cargo/") => "→",
+			_ => " ",
+		};
+
+		frames.push(match location {
+			Some(location) => format!("{}{:>3}: {}\n        at {}", marker, number, symbol, location),
+			None => format!("{}{:>3}: {}", marker, number, symbol),
+		});
+	}
+
+	frames
+}
+
+/// Backticks would close the code block the output is put in.
+pub fn escape(output: &str) -> String {
+	output.replace('`', "\"")
+}
+
+/// Pulls the `test foo ... ok` lines out of libtest's output. Gives `None` if
+/// there weren't any, like when the tests didn't compile.
+fn test_summary(stdout: &str) -> Option<String> {
+	let mut lines = Vec::new();
+	let (mut passed, mut failed, mut ignored) = (0, 0, 0);
+
+	for line in stdout.lines() {
+		let (name, outcome) = match line.strip_prefix("test ").and_then(|l| l.rsplit_once(" ... ")) {
+			Some(test) => test,
+			None => continue,
+		};
+
+		let icon = match outcome {
+			"ok" => { passed += 1; "✅" },
+			"FAILED" => { failed += 1; "❌" },
+			o if o.starts_with("ignored") => { ignored += 1; "⏭️" },
+			_ => continue,
+		};
+		lines.push(format!("{} `{}`", icon, name));
+	}
+
+	if lines.is_empty() {
+		return None;
+	}
+
+	// Only failing tests have anything worth showing after the list.
+	let failures = stdout.split_once("\nfailures:\n").map(|(_, f)| f).unwrap_or("");
+	let failures = failures.split("\nfailures:\n").next().unwrap_or("").trim();
+
+	let mut summary = format!(
+		"**Tests:** {} passed, {} failed, {} ignored\n{}",
+		passed,
+		failed,
+		ignored,
+		lines.join("\n")
+	);
+	if !failures.is_empty() {
+		summary.push_str(&format!("\n**Failures:** ```{}```", escape(failures)));
+	}
+	Some(summary)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const NEW_PANIC: &str = "   Compiling playground v0.0.1\nthread 'main' panicked at src/main.rs:2:5:
+oh no
+it broke
+stack backtrace:
+   0: rust_begin_unwind
+             at /rustc/abc/library/std/src/panicking.rs:645:5
+   1: core::panicking::panic_fmt
+             at /rustc/abc/library/core/src/panicking.rs:72:14
+   2: playground::inner
+             at ./src/main.rs:2:5
+   3: playground::main
+             at ./src/main.rs:6:5
+   4: core::ops::function::FnOnce::call_once
+             at /rustc/abc/library/core/src/ops/function.rs:250:5
+note: Some details are omitted, run with `RUST_BACKTRACE=full` for a verbose backtrace.
+";
+
+	#[test]
+	fn parses_new_style_panics() {
+		let panic = Panic::parse(NEW_PANIC).unwrap();
+		assert_eq!(&NEW_PANIC[..panic.start], "   Compiling playground v0.0.1\n");
+		assert_eq!(panic.location, "src/main.rs:2:5");
+		assert_eq!(panic.message, "oh no\nit broke");
+	}
+
+	#[test]
+	fn parses_old_style_panics() {
+		let panic = Panic::parse("thread 'main' panicked at 'oh no', src/main.rs:2:5\nnote: run with `RUST_BACKTRACE=1`").unwrap();
+		assert_eq!(panic.location, "src/main.rs:2:5");
+		assert_eq!(panic.message, "oh no");
+		assert!(panic.frames.is_empty());
+	}
+
+	#[test]
+	fn keeps_only_user_frames() {
+		let panic = Panic::parse(NEW_PANIC).unwrap();
+		assert_eq!(panic.frames, [
+			"→  2: playground::inner\n        at ./src/main.rs:2:5",
+			"→  3: playground::main\n        at ./src/main.rs:6:5",
+		]);
+	}
+
+	#[test]
+	fn no_panic_no_split() {
+		assert!(Panic::parse("warning: unused variable").is_none());
+		assert_eq!(stderr(true, "warning: unused"), "**Err:** ```warning: unused```");
+	}
+}
@@ -0,0 +1,208 @@
+use tracing::{error, info};
+use twilight_http::Client;
+use twilight_model::{
+	application::component::Component,
+	channel::Message,
+	id::{marker::{ChannelMarker, MessageMarker}, Id},
+};
+
+use crate::config::{self, OnDelete};
+use crate::error::BotError;
+use crate::run;
+use crate::storage::{self, Replies};
+
+pub fn get(message: Id<MessageMarker>) -> Option<Replies> {
+	storage::get().replies(message).unwrap_or_else(|why| {
+		error!("Failed to look up the replies to a message: {}", why);
+		None
+	})
+}
+
+pub fn set(message: Id<MessageMarker>, replies: Replies) {
+	if let Err(why) = storage::get().set_replies(message, &replies) {
+		error!("Failed to save the replies to a message: {}", why);
+	}
+}
+
+pub fn remove(message: Id<MessageMarker>) -> Option<Replies> {
+	storage::get().remove_replies(message).unwrap_or_else(|why| {
+		error!("Failed to forget the replies to a message: {}", why);
+		None
+	})
+}
+
+pub fn source_hash(content: &str) -> u64 {
+	storage::stable_hash(content)
+}
+
+/// Cleans up after a message in a run channel was deleted. Its thread is
+/// deleted or archived depending on `on_delete`, notices are always deleted
+/// since there's nothing left for them to be about.
+pub async fn clean_up(http: &Client, channel: Id<ChannelMarker>, message: Id<MessageMarker>) {
+	let replies = match remove(message) {
+		Some(replies) => replies,
+		None => return,
+	};
+
+	for notice in replies.notices {
+		if http.delete_message(channel, notice).exec().await.is_err() {
+			error!("Failed to delete a notice for a deleted message.");
+		}
+	}
+
+	if let Some(thread) = replies.thread {
+		let cleaned = match config::get().on_delete {
+			OnDelete::Delete => http.delete_channel(thread).exec().await.map(|_| ()),
+			OnDelete::Archive => http.update_thread(thread).archived(true).locked(true).exec().await.map(|_| ()),
+		};
+
+		if cleaned.is_err() {
+			error!("Failed to clean up the thread for a deleted message.");
+		}
+	}
+}
+
+/// The results for one run of a message, as they get posted in its thread.
+pub struct ThreadReplies<'a> {
+	message: &'a Message,
+	thread: Option<Id<ChannelMarker>>,
+	/// Results from before the message was edited, which get edited in order.
+	old: std::vec::IntoIter<Id<MessageMarker>>,
+	old_notices: Vec<Id<MessageMarker>>,
+	posted: Vec<Id<MessageMarker>>,
+	notices: Vec<Id<MessageMarker>>,
+	runs: u32,
+}
+
+impl<'a> ThreadReplies<'a> {
+	/// Starts a run of `message`, picking up where its last run left off if it's been edited.
+	pub fn new(message: &'a Message) -> Self {
+		let previous = get(message.id);
+
+		ThreadReplies {
+			message,
+			thread: previous.as_ref().and_then(|p| p.thread),
+			runs: previous.as_ref().map_or(1, |p| p.runs + 1),
+			old_notices: previous.as_ref().map(|p| p.notices.clone()).unwrap_or_default(),
+			old: previous.map(|p| p.results).unwrap_or_default().into_iter(),
+			posted: Vec::new(),
+			notices: Vec::new(),
+		}
+	}
+
+	/// Whether anything was posted for this run.
+	pub fn any(&self) -> bool {
+		!self.posted.is_empty()
+	}
+
+	/// Replies to the message in its channel, for things that aren't results.
+	pub async fn notice(&mut self, http: &Client, content: &str) -> Result<(), BotError> {
+		self.notice_with(http, content, &[]).await
+	}
+
+	/// A notice with buttons.
+	pub async fn notice_with(&mut self, http: &Client, content: &str, components: &[Component]) -> Result<(), BotError> {
+		let notice = http.create_message(self.message.channel_id)
+			.reply(self.message.id)
+			.content(content)?
+			.components(components)?
+			.exec()
+			.await?
+			.model()
+			.await?;
+
+		self.notices.push(notice.id);
+		Ok(())
+	}
+
+	/// Posts a result, making the thread if this is the first one, or edits the
+	/// matching result from before the message was edited. Results too long for
+	/// a message are posted as an attachment.
+	pub async fn post(&mut self, http: &Client, content: &str, components: &[Component]) -> Result<(), BotError> {
+		let content = match self.runs {
+			1 => content.to_string(),
+			runs => format!("*edited, run #{}*\n{}", runs, content),
+		};
+		let filename = format!("{}-{}.txt", self.message.author.id, self.message.id);
+		let (content, attachments) = run::fit(&content, &filename);
+
+		let thread_id = match self.thread {
+			Some(thread_id) => thread_id,
+			None => {
+				let new_thread_channel = http
+					.create_thread_from_message(
+						self.message.channel_id,
+						self.message.id,
+						&format!("{}-{}", self.message.author.id, self.message.id),
+					)?
+					.exec()
+					.await?
+					.model()
+					.await?;
+				info!("Successfully created a thread.");
+				*self.thread.insert(new_thread_channel.id)
+			}
+		};
+
+		let result: Result<_, BotError> = match self.old.next() {
+			Some(old) => http
+				.update_message(thread_id, old)
+				.content(Some(content))?
+				// Whatever was attached to the old result is replaced, not added to.
+				.keep_attachment_ids(&[])
+				.attachments(&attachments)?
+				.components(Some(components))?
+				.exec()
+				.await
+				.map(|_| old)
+				.map_err(Into::into),
+			None => match http
+				.create_message(thread_id)
+				.content(content)?
+				.attachments(&attachments)?
+				.components(components)?
+				.exec()
+				.await
+			{
+				Ok(response) => response.model().await.map(|m| m.id).map_err(Into::into),
+				Err(why) => Err(why.into()),
+			},
+		};
+
+		match result {
+			Ok(id) => self.posted.push(id),
+			Err(_) => error!("Failed to send a result to a thread."),
+		}
+		Ok(())
+	}
+
+	/// Removes results and notices from before the edit that this run didn't
+	/// need, and remembers what was posted for the next edit.
+	pub async fn finish(self, http: &Client) {
+		if let Some(thread) = self.thread {
+			for old in self.old {
+				if http.delete_message(thread, old).exec().await.is_err() {
+					error!("Failed to delete an old result.");
+				}
+			}
+		}
+
+		for old in self.old_notices {
+			if http.delete_message(self.message.channel_id, old).exec().await.is_err() {
+				error!("Failed to delete an old notice.");
+			}
+		}
+
+		if self.thread.is_some() || !self.notices.is_empty() {
+			set(self.message.id, Replies {
+				thread: self.thread,
+				results: self.posted,
+				notices: self.notices,
+				runs: self.runs,
+				source: source_hash(&self.message.content),
+			});
+		} else {
+			remove(self.message.id);
+		}
+	}
+}
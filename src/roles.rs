@@ -0,0 +1,130 @@
+use async_trait::async_trait;
+use tracing::{error, info};
+use twilight_http::{request::AuditLogReason, Client};
+use twilight_model::{
+	application::{
+		component::{action_row::ActionRow, button::{Button, ButtonStyle}, Component},
+		interaction::MessageComponentInteraction,
+	},
+	http::interaction::{InteractionResponse, InteractionResponseType},
+	id::{marker::{ChannelMarker, RoleMarker}, Id},
+};
+
+use crate::audit;
+use crate::config;
+use crate::error::BotError;
+use crate::handler::{ComponentHandler, Context, Invoker};
+
+/// The buttons on the role menus from `button_menus`, their ids are the role they give.
+pub struct RoleButtons;
+
+#[async_trait]
+impl ComponentHandler for RoleButtons {
+	fn handles(&self, custom_id: &str) -> bool {
+		custom_id.parse::<u64>().is_ok()
+	}
+
+	async fn run(&self, ctx: &Context, msgcmp: &MessageComponentInteraction, invoker: &Invoker) -> Result<(), BotError> {
+		ctx.interaction()
+			.create_response(
+				msgcmp.id,
+				&msgcmp.token,
+				&InteractionResponse {
+					kind: InteractionResponseType::DeferredUpdateMessage,
+					data: None,
+				}
+			)
+			.exec()
+			.await?;
+
+		let role_id = msgcmp.data.custom_id.parse::<u64>().ok()
+			.and_then(Id::<RoleMarker>::new_checked)
+			.ok_or_else(|| BotError::Validation(String::from("Failed to get the role id. Sorry.")))?;
+
+		let mut roles = invoker.roles.clone();
+
+		let added = !roles.contains(&role_id);
+		let message = if !added {
+			let new_roles = roles.iter().filter(|e| *e != &role_id).copied().collect::<Vec<Id<RoleMarker>>>();
+			ctx.http.update_guild_member(invoker.guild, invoker.user.id)
+				.roles(&new_roles).exec().await?;
+			format!("<:ferrischeck:957417376314429490> removed <@&{}>", role_id.get())
+		} else {
+			roles.push(role_id);
+			ctx.http.update_guild_member(invoker.guild, invoker.user.id)
+				.roles(&roles).exec().await?;
+			format!("<:ferrischeck:957417376314429490> added <@&{}>", role_id.get())
+		};
+
+		ctx.ephemeral(&msgcmp.token, &message).await?;
+
+		let label = config::get().guild(invoker.guild)
+			.and_then(|guild| guild.button_menus.iter().find(|menu| menu.channel_id == msgcmp.channel_id.get()))
+			.and_then(|menu| menu.roles.iter().find(|role| role.id == role_id.get()))
+			.map(|role| role.label.clone());
+		audit::log(&ctx.http, invoker.guild, audit::Entry::Role {
+			user: invoker.user.id,
+			role: role_id,
+			added,
+			menu: (msgcmp.channel_id, msgcmp.message.id),
+			label,
+		}).await;
+		Ok(())
+	}
+}
+
+/// Posts every guild's role button menus, replacing the bot's old ones.
+pub async fn send_button_menus(http: &Client) -> Result<(), BotError> {
+	let config = config::get();
+	for button_menu in config.guilds.values().flat_map(|guild| &guild.button_menus) {
+		let channel_id = Id::<ChannelMarker>::new(button_menu.channel_id);
+		let channel_messages = http
+			.channel_messages(channel_id)
+			.limit(config.settings.messages_to_check as u16)?
+			.exec()
+			.await?;
+
+		for message in channel_messages.models().await? {
+			if message.author.bot && message.author.id.get() == config::get().bot_id {
+				if message.content == button_menu.message {
+					match http.delete_message(channel_id, message.id).reason("Sending new button menu")?.exec().await {
+						Ok(_) => info!("Should've deleted?"),
+						Err(_) => error!("Failed to delete a bot's message while starting"),
+					}
+				}
+				continue;
+			}
+
+			match http.create_message(channel_id)
+				.content(&button_menu.message)?
+				.components(&[
+					Component::ActionRow(
+						ActionRow {
+							components: button_menu.roles.iter().map(|r| {
+								let role_label = format!("{}", r.id);
+								Component::Button(Button {
+							        custom_id: Some(role_label),
+							        disabled: false,
+							        emoji: None,
+							        label: Some(r.label.clone()),
+							        style: match r.style {
+										1 => ButtonStyle::Primary,
+										2 => ButtonStyle::Secondary,
+										3 => ButtonStyle::Success,
+										4 => ButtonStyle::Danger,
+										_ => ButtonStyle::Secondary
+									},
+							        url: None,
+							    })
+							}).collect::<Vec<Component>>()
+						})]
+				)?.exec().await {
+			        Ok(_) => {},
+			        Err(_) => error!("Failed to send a button menu."),
+			    }
+			break;
+		}
+	}
+
+	Ok(())
+}
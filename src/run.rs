@@ -0,0 +1,631 @@
+use std::{sync::Arc, time::Instant};
+
+use async_trait::async_trait;
+use tracing::error;
+use twilight_http::{request::channel::reaction::RequestReactionType, Client};
+use twilight_model::{
+	application::{
+		command::{BaseCommandOptionData, ChoiceCommandOptionData, Command, CommandOption, CommandOptionChoice, CommandType},
+		component::{action_row::ActionRow, button::{Button, ButtonStyle}, text_input::TextInputStyle, Component, TextInput},
+		interaction::{application_command::CommandOptionValue, modal::ModalSubmitInteraction, ApplicationCommand, MessageComponentInteraction},
+	},
+	channel::{message::MessageFlags, Message},
+	http::{attachment::Attachment, interaction::{InteractionResponse, InteractionResponseData, InteractionResponseType}},
+	id::{marker::{ApplicationMarker, ChannelMarker, GuildMarker, UserMarker}, Id},
+};
+use twilight_util::builder::command::CommandBuilder;
+
+use crate::attachment;
+use crate::backend::{BackendError, ExecutionBackend, Sharing};
+use crate::code::{self, RunOptions};
+use crate::config;
+use crate::error::BotError;
+use crate::handler::{ComponentHandler, Context, Invoker, MessageCommand, ModalHandler, SlashCommand};
+use crate::limits;
+use crate::play::{Crate, CrateFile, Playground};
+use crate::queue;
+use crate::render;
+use crate::screen;
+use crate::storage;
+
+/// `/run`, opens the "Rust Runner 9000" modal with the options picked.
+pub struct RunCommand;
+
+#[async_trait]
+impl SlashCommand for RunCommand {
+	fn command(&self) -> Command {
+		CommandBuilder::new(
+			"run".into(),
+			"Run some Rust out side of defined run-my-rust channels.".into(),
+			CommandType::ChatInput,
+		)
+		.option(choice_option("channel", "Which release channel to use. Defaults to stable.", &code::Channel::ALL.map(|c| c.as_str())))
+		.option(choice_option("mode", "Build in debug or release mode, or run the tests. Defaults to debug.", &code::Mode::ALL.map(|m| m.as_str())))
+		.option(choice_option("edition", "Which edition to use. Defaults to 2021.", &code::Edition::ALL.map(|e| e.as_str())))
+		.option(CommandOption::Boolean(BaseCommandOptionData {
+			description: String::from("Show a backtrace if the code panics."),
+			name: String::from("backtrace"),
+			required: false,
+		}))
+		.option(choice_option("tool", "Something to go over the code with instead of running it.", &code::Tool::ALL.map(|t| t.as_str())))
+		.build()
+	}
+
+	async fn run(&self, ctx: &Context, cmd: &ApplicationCommand, _invoker: &Invoker) -> Result<(), BotError> {
+		let mut options = RunOptions::default();
+		for option in &cmd.data.options {
+			let value = match &option.value {
+				CommandOptionValue::String(value) => value.clone(),
+				CommandOptionValue::Boolean(value) => value.to_string(),
+				_ => continue,
+			};
+			options.set(&format!("{}={}", option.name, value)).map_err(BotError::Validation)?;
+		}
+
+		ctx.interaction()
+			.create_response(cmd.id, &cmd.token, &modal(&options, None))
+			.exec()
+			.await?;
+		Ok(())
+	}
+}
+
+/// The modal from `/run` being submitted.
+pub struct RunModal;
+
+#[async_trait]
+impl ModalHandler for RunModal {
+	fn handles(&self, custom_id: &str) -> bool {
+		custom_id.starts_with("run-my-rust")
+	}
+
+	async fn run(&self, ctx: &Context, modal: &ModalSubmitInteraction, invoker: &Invoker) -> Result<(), BotError> {
+		// The options picked in `/run` ride along in the modal's id, `run-my-rust:nightly,release,2018`.
+		let flags = modal.data.custom_id.trim_start_matches("run-my-rust").trim_start_matches(':');
+		let options = RunOptions::from_flags(flags.split(',').filter(|f| !f.is_empty())).map_err(BotError::Validation)?;
+
+		let to_run = modal.data.components.iter()
+			.flat_map(|row| &row.components)
+			.find(|comp| comp.custom_id == "code-to-run");
+
+		if let Some(comp) = to_run {
+			pay(invoker, modal.channel_id, 1)?;
+			if screen::check_interaction(ctx, modal.id, &modal.token, invoker, modal.channel_id, &comp.value, Some(options)).await? {
+				let filename = format!("{}-{}.txt", invoker.guild.get(), invoker.user.id.get());
+				for_interaction(ctx, &modal.token, invoker, modal.channel_id, &comp.value, &options, &filename).await?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// `run-message`, runs a message's code the way the run channels do, with the
+/// results as followups. Anything that went wrong is only shown to whoever asked.
+pub struct RunMessage;
+
+#[async_trait]
+impl MessageCommand for RunMessage {
+	fn name(&self) -> String {
+		String::from("run-message")
+	}
+
+	async fn run(&self, ctx: &Context, cmd: &ApplicationCommand, invoker: &Invoker, message: &Message) -> Result<(), BotError> {
+		let loading = RequestReactionType::Unicode { name: "🌀" };
+		let cross = RequestReactionType::Unicode { name: "❌" };
+		let success = RequestReactionType::Unicode { name: "✅" };
+
+		let blocks = code::blocks(&message.content);
+		let uploads = attachment::runnable(&message.attachments);
+		if blocks.is_empty() && uploads == 0 {
+			return Err(BotError::Validation(String::from("That message doesn't have any ```rust code blocks, `.rs` files or crates in it.")));
+		}
+
+		let runs = (blocks.len() + uploads).min(code::MAX_BLOCKS) as u32;
+		pay(invoker, cmd.channel_id, runs)?;
+
+		// Answered with what's being run rather than deferred, since the first
+		// followup after a defer takes its place and the problems and screening
+		// notices below have to stay only for whoever asked.
+		ctx.interaction()
+			.create_response(cmd.id, &cmd.token, &InteractionResponse {
+				kind: InteractionResponseType::ChannelMessageWithSource,
+				data: Some(InteractionResponseData {
+					content: Some(format!(
+						"🌀 Running the code in https://discord.com/channels/{}/{}/{}",
+						invoker.guild, message.channel_id, message.id
+					)),
+					..Default::default()
+				}),
+			})
+			.exec()
+			.await?;
+		let _permit = queue::wait_for_interaction(&ctx.http, &cmd.token, invoker.user.id).await?;
+
+		let (mut blocks, crates, problems) = split_uploads(blocks, attachment::uploads(&message.attachments).await);
+
+		ctx.http.create_reaction(cmd.channel_id, message.id, &loading)
+			.exec()
+			.await?;
+
+		for why in &problems {
+			ctx.ephemeral(&cmd.token, why).await?;
+		}
+
+		let filename = format!("{}-{}.txt", invoker.guild.get(), invoker.user.id.get());
+		let ran_by = RanBy { user: invoker.user.id, guild: Some(invoker.guild), channel: cmd.channel_id };
+		blocks.truncate(code::MAX_BLOCKS);
+		let crates = crates.into_iter().take(code::MAX_BLOCKS - blocks.len());
+		// Only gets a ✅ if something actually ran, like in the run channels.
+		let mut ran = false;
+
+		for block in blocks {
+			let options = match RunOptions::from_flags(block.flags) {
+				Ok(options) => options,
+				Err(why) => {
+					ctx.ephemeral(&cmd.token, &why).await?;
+					continue
+				}
+			};
+			if !screen::check_followup(ctx, &cmd.token, invoker, cmd.channel_id, &block.code, Some(options)).await? {
+				continue;
+			}
+
+			let playground = Playground::new(&block.code, &options);
+			match code(&ctx.backend, &block.code, &playground, &options, ran_by).await {
+				Ok((content, execution)) => {
+					let share = share_button(&ctx.backend, &playground, execution).await;
+					followup_result(&ctx.http, &cmd.token, &content, &share, &filename).await?;
+					ran = true;
+				}
+				Err(why) => {
+					ctx.http.create_reaction(cmd.channel_id, message.id, &cross)
+						.exec()
+						.await?;
+					ctx.ephemeral(&cmd.token, &failed(why)).await?;
+				}
+			}
+		}
+
+		for krate in crates {
+			if !screen::check_followup(ctx, &cmd.token, invoker, cmd.channel_id, &screen::crate_code(&krate), None).await? {
+				continue;
+			}
+
+			let krate = Crate::new(krate, &RunOptions::default());
+			match self::krate(&ctx.backend, &krate, ran_by).await {
+				Ok(content) => {
+					let share = crate_share_button(&ctx.backend, &krate).await;
+					followup_result(&ctx.http, &cmd.token, &content, &share, &filename).await?;
+					ran = true;
+				}
+				Err(why) => {
+					ctx.http.create_reaction(cmd.channel_id, message.id, &cross)
+						.exec()
+						.await?;
+					ctx.ephemeral(&cmd.token, &failed(why)).await?;
+				}
+			}
+		}
+
+		if ran {
+			ctx.http.create_reaction(cmd.channel_id, message.id, &success)
+				.exec()
+				.await?;
+		}
+		Ok(())
+	}
+}
+
+/// A string option on a command that has to be one of `values`.
+pub fn choice_option(name: &str, description: &str, values: &[&str]) -> CommandOption {
+	CommandOption::String(ChoiceCommandOptionData {
+		autocomplete: false,
+		choices: values.iter().map(|v| CommandOptionChoice::String {
+			name: v.to_string(),
+			value: v.to_string(),
+		}).collect(),
+		description: description.to_string(),
+		name: name.to_string(),
+		required: false,
+	})
+}
+
+/// Takes `runs` from the rate limits for an interaction. It's done before the
+/// interaction's answered, so being told to slow down is only for whoever it was.
+pub fn pay(invoker: &Invoker, channel: Id<ChannelMarker>, runs: u32) -> Result<(), BotError> {
+	limits::take(invoker.user.id, channel, Some(invoker.guild), runs).map_err(BotError::RateLimited)
+}
+
+/// Runs code for an interaction that's been paid for and answered (or
+/// deferred) once it gets a turn, with the result as a followup.
+pub async fn for_interaction(
+	ctx: &Context,
+	token: &str,
+	invoker: &Invoker,
+	channel: Id<ChannelMarker>,
+	code: &str,
+	options: &RunOptions,
+	filename: &str,
+) -> Result<(), BotError> {
+	let _permit = queue::wait_for_interaction(&ctx.http, token, invoker.user.id).await?;
+
+	let playground = Playground::new(code, options);
+	let ran_by = RanBy { user: invoker.user.id, guild: Some(invoker.guild), channel };
+	let (content, execution) = self::code(&ctx.backend, code, &playground, options, ran_by).await?;
+
+	let share = share_button(&ctx.backend, &playground, execution).await;
+	followup_result(&ctx.http, token, &content, &share, filename).await
+}
+
+/// Sorts a message's attachments in with its code blocks: `.rs` files run like
+/// blocks, crates on their own, and anything that couldn't be used is why not.
+pub fn split_uploads(
+	mut blocks: Vec<code::CodeBlock<'_>>,
+	uploads: Vec<Result<attachment::Upload, String>>,
+) -> (Vec<code::CodeBlock<'_>>, Vec<Vec<CrateFile>>, Vec<String>) {
+	let (mut crates, mut problems) = (Vec::new(), Vec::new());
+
+	for upload in uploads {
+		match upload {
+			Ok(attachment::Upload::Source(code)) => blocks.push(code::CodeBlock { flags: vec![], code }),
+			Ok(attachment::Upload::Crate(files)) => crates.push(files),
+			Err(why) => problems.push(why),
+		}
+	}
+
+	(blocks, crates, problems)
+}
+
+/// Who ran something, and where.
+#[derive(Clone, Copy)]
+pub struct RanBy {
+	pub user: Id<UserMarker>,
+	pub guild: Option<Id<GuildMarker>>,
+	pub channel: Id<ChannelMarker>,
+}
+
+/// Runs the code, or whichever tool was picked to go over it, and renders the
+/// reply. `code` is what was given to run, before `Playground` wrapped it.
+/// Gives back the reply and the id the run was recorded with, if it was.
+pub async fn code(
+	backend: &Arc<dyn ExecutionBackend>,
+	code: &str,
+	playground: &Playground,
+	options: &RunOptions,
+	ran_by: RanBy,
+) -> Result<(String, Option<i64>), BackendError> {
+	let started = Instant::now();
+	let (success, content) = match options.tool {
+		Some(tool) => {
+			let result = backend.tool(tool, playground).await?;
+			(result.success, render::tool(tool, &result))
+		}
+		None => {
+			let result = backend.execute(playground).await?;
+			(result.success, render::result(playground, &result))
+		}
+	};
+
+	let execution = record(ran_by, code, options.to_flags(), success, started);
+	Ok((content, execution))
+}
+
+/// Builds and runs an uploaded crate, and renders the reply.
+pub async fn krate(
+	backend: &Arc<dyn ExecutionBackend>,
+	krate: &Crate,
+	ran_by: RanBy,
+) -> Result<String, BackendError> {
+	let started = Instant::now();
+	let result = backend.execute_crate(krate).await?;
+
+	// There's no one piece of code to remember for a crate, so it's remembered by what's in it.
+	let files = krate.files.iter().map(|f| format!("// {}", f.path)).collect::<Vec<_>>().join("\n");
+	record(ran_by, &files, String::from("crate"), result.success, started);
+	Ok(render::crate_result(krate, &result))
+}
+
+/// What to tell someone when running their code didn't work at all.
+pub fn failed(why: BackendError) -> String {
+	let why = BotError::Playground(why);
+	error!("Failed to run something: {}", why);
+	why.user_message()
+}
+
+fn record(ran_by: RanBy, code: &str, flags: String, success: bool, started: Instant) -> Option<i64> {
+	let execution = storage::Execution {
+		user: ran_by.user,
+		guild: ran_by.guild,
+		channel: ran_by.channel,
+		code_hash: storage::code_hash(code),
+		code: code.to_string(),
+		flags,
+		success,
+		duration_ms: started.elapsed().as_millis() as u64,
+	};
+
+	storage::get().record_execution(&execution)
+		.map_err(|why| error!("Failed to record an execution: {}", why))
+		.ok()
+}
+
+/// The button a result gets for its code, going by the backend's `Sharing`:
+/// an "Open in playground" link, or a "Share" button that only makes one once
+/// it's pressed (which needs the run to have been recorded).
+pub async fn share_button(backend: &Arc<dyn ExecutionBackend>, playground: &Playground, execution: Option<i64>) -> Vec<Component> {
+	let button = match backend.sharing() {
+		Sharing::Nowhere => return vec![],
+		Sharing::OnPress => match execution {
+			Some(id) => Button {
+				custom_id: Some(format!("share:{}", id)),
+				disabled: false,
+				emoji: None,
+				label: Some(String::from("Share")),
+				style: ButtonStyle::Secondary,
+				url: None,
+			},
+			None => return vec![],
+		},
+		Sharing::Upfront => match backend.share(playground).await {
+			Ok(Some(url)) => open_button(url),
+			Ok(None) => return vec![],
+			Err(why) => {
+				error!("Failed to share a result's code: {}", why);
+				return vec![];
+			}
+		},
+	};
+	vec![Component::ActionRow(ActionRow { components: vec![Component::Button(button)] })]
+}
+
+/// `share_button` for a crate, which is shared as its `.rs` files one after the other.
+pub async fn crate_share_button(backend: &Arc<dyn ExecutionBackend>, krate: &Crate) -> Vec<Component> {
+	let playground = Playground::new(&screen::crate_code(&krate.files), &RunOptions::default());
+	share_button(backend, &playground, None).await
+}
+
+fn open_button(url: String) -> Button {
+	Button {
+		custom_id: None,
+		disabled: false,
+		emoji: None,
+		label: Some(String::from("Open in playground")),
+		style: ButtonStyle::Link,
+		url: Some(url),
+	}
+}
+
+/// The "Share" button on a result being pressed. Saves the code and gives
+/// whoever pressed it a link to it.
+pub struct ShareButton;
+
+#[async_trait]
+impl ComponentHandler for ShareButton {
+	fn handles(&self, custom_id: &str) -> bool {
+		custom_id.starts_with("share:")
+	}
+
+	async fn run(&self, ctx: &Context, msgcmp: &MessageComponentInteraction, invoker: &Invoker) -> Result<(), BotError> {
+		ctx.defer_ephemeral(msgcmp.id, &msgcmp.token).await?;
+
+		let id = msgcmp.data.custom_id.trim_start_matches("share:").parse().ok();
+		// Results are public, but runs from other guilds aren't this button's to share.
+		let execution = match id.map(|id| storage::get().execution(id)).transpose()?.flatten() {
+			Some(execution) if execution.value.guild == Some(invoker.guild) => execution.value,
+			_ => return ctx.ephemeral(&msgcmp.token, "That run isn't around anymore.").await,
+		};
+		let options = RunOptions::from_flags(execution.flags.split(',').filter(|f| !f.is_empty())).map_err(BotError::Validation)?;
+
+		let url = match ctx.backend.share(&Playground::new(&execution.code, &options)).await {
+			Ok(Some(url)) => url,
+			Ok(None) => return ctx.ephemeral(&msgcmp.token, "There's nowhere to share code to.").await,
+			Err(why) => return ctx.ephemeral(&msgcmp.token, &failed(why)).await,
+		};
+
+		ctx.interaction()
+			.create_followup(&msgcmp.token)
+			.content("Here you go.")?
+			.components(&[Component::ActionRow(ActionRow { components: vec![Component::Button(open_button(url))] })])?
+			.flags(MessageFlags::EPHEMERAL)
+			.exec()
+			.await?;
+		Ok(())
+	}
+}
+
+/// Output longer than a message can be goes in an attachment named `filename`
+/// instead, with a note saying so as the message.
+pub fn fit<'a>(content: &'a str, filename: &str) -> (&'a str, Vec<Attachment>) {
+	if content.len() <= 2000 {
+		return (content, vec![]);
+	}
+
+	(
+		"<:ferrisbanne:958831785922416780> The output was too long. So here you go. Have an attachment. Is this what you wanted?",
+		vec![Attachment {
+			description: None,
+			file: content.as_bytes().to_vec(),
+			filename: filename.to_string(),
+		}],
+	)
+}
+
+/// Sends a result as a followup, as an attachment if it's too long for a message.
+pub async fn followup_result(
+	http: &Client,
+	token: &str,
+	content: &str,
+	components: &[Component],
+	filename: &str,
+) -> Result<(), BotError> {
+	let (content, attachments) = fit(content, filename);
+	http.interaction(Id::<ApplicationMarker>::new(config::get().bot_id))
+		.create_followup(token)
+		.content(content)?
+		.attachments(&attachments)?
+		.components(components)?
+		.exec()
+		.await?;
+	Ok(())
+}
+
+/// The "Rust Runner 9000" modal `/run` opens, with the options riding along in
+/// its id as `run-my-rust:nightly,release,2018`. `code` fills the box in.
+pub fn modal(options: &RunOptions, code: Option<String>) -> InteractionResponse {
+	InteractionResponse {
+		kind: InteractionResponseType::Modal,
+		data: Some(InteractionResponseData {
+			components: Some(vec![
+				Component::ActionRow(ActionRow {
+					components: vec![
+						Component::TextInput(TextInput {
+							custom_id: String::from("code-to-run"),
+							label: String::from("Code to run"),
+							max_length: None,
+							min_length: None,
+							placeholder: None,
+							required: Some(true),
+							style: TextInputStyle::Paragraph,
+							value: code,
+						})
+					]
+				})
+			]),
+			custom_id: Some(format!("run-my-rust:{}", options.to_flags())),
+			title: Some(String::from("Rust Runner 9000")),
+			tts: Some(false),
+			..Default::default()
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Once;
+
+	use super::*;
+	use crate::backend::FakeBackend;
+	use crate::code::Tool;
+
+	/// Runs are recorded in the real (global) database, so it's opened once in
+	/// the temp dir. Each test runs as its own user so they don't see each other's.
+	fn ran_by(user: u64) -> RanBy {
+		static STORAGE: Once = Once::new();
+		STORAGE.call_once(|| {
+			let dir = std::env::temp_dir().join(format!("run-my-rust-test-run-{}", std::process::id()));
+			storage::init(&dir).unwrap();
+		});
+		RanBy { user: Id::new(user), guild: Some(Id::new(1)), channel: Id::new(2) }
+	}
+
+	fn backend(output: Option<(bool, &str, &str)>, share: Option<&str>) -> Arc<FakeBackend> {
+		Arc::new(FakeBackend {
+			output: output.map(|(success, stdout, stderr)| (success, stdout.to_string(), stderr.to_string())),
+			share: share.map(String::from),
+			..Default::default()
+		})
+	}
+
+	async fn run(backend: &Arc<FakeBackend>, code: &str, options: &RunOptions, ran_by: RanBy) -> Result<(String, Option<i64>), BackendError> {
+		let dyn_backend: Arc<dyn ExecutionBackend> = backend.clone();
+		super::code(&dyn_backend, code, &Playground::new(code, options), options, ran_by).await
+	}
+
+	#[tokio::test]
+	async fn runs_and_records() {
+		let fake = backend(Some((true, "hi", "")), None);
+		let (content, id) = run(&fake, "fn main() { println!(\"hi\"); }", &RunOptions::default(), ran_by(101)).await.unwrap();
+		assert!(content.starts_with("Result: true\nStds:\n**Out:** hi"));
+
+		let executions = storage::get().executions_by(Id::new(101), Id::new(1), 10, 0).unwrap();
+		assert_eq!(executions.len(), 1);
+		assert_eq!(Some(executions[0].id), id);
+		assert_eq!(executions[0].value.code, "fn main() { println!(\"hi\"); }");
+		assert!(executions[0].value.success);
+	}
+
+	#[tokio::test]
+	async fn expressions_get_a_main() {
+		let fake = backend(Some((true, "4", "")), None);
+		run(&fake, "2 + 2", &RunOptions::default(), ran_by(102)).await.unwrap();
+
+		let seen = fake.seen.lock().unwrap();
+		assert_eq!(seen.as_slice(), ["fn main() { println!(\"{:?}\", { 2 + 2 } ) }"]);
+		// What was recorded is what was given, not the wrapped up version.
+		drop(seen);
+		assert_eq!(storage::get().executions_by(Id::new(102), Id::new(1), 10, 0).unwrap()[0].value.code, "2 + 2");
+	}
+
+	#[tokio::test]
+	async fn tools_are_rendered_as_tools() {
+		let fake = backend(Some((true, "fn main() {}", "")), None);
+		let options = RunOptions { tool: Some(Tool::Expand), ..Default::default() };
+		let (content, _) = run(&fake, "fn main() {}", &options, ran_by(103)).await.unwrap();
+		assert!(content.starts_with("**Expanded:**"));
+		assert_eq!(storage::get().executions_by(Id::new(103), Id::new(1), 10, 0).unwrap()[0].value.flags, options.to_flags());
+	}
+
+	#[tokio::test]
+	async fn backend_errors_are_not_recorded() {
+		let fake = backend(None, None);
+		let why = run(&fake, "fn main() {}", &RunOptions::default(), ran_by(104)).await.unwrap_err();
+		assert_eq!(failed(why), "❌ Something went wrong running that, try again in a bit.");
+		assert!(storage::get().executions_by(Id::new(104), Id::new(1), 10, 0).unwrap().is_empty());
+	}
+
+	#[tokio::test]
+	async fn crates_are_recorded_by_their_files() {
+		let fake = backend(Some((false, "", "error[E0425]: cannot find value `x`")), None);
+		let files = vec![
+			CrateFile { path: String::from("Cargo.toml"), contents: vec![] },
+			CrateFile { path: String::from("src/main.rs"), contents: vec![] },
+		];
+		let dyn_backend: Arc<dyn ExecutionBackend> = fake.clone();
+		let content = krate(&dyn_backend, &Crate::new(files, &RunOptions::default()), ran_by(105)).await.unwrap();
+		assert!(content.starts_with("Result: false"));
+		assert!(content.contains("E0425"));
+
+		let executions = storage::get().executions_by(Id::new(105), Id::new(1), 10, 0).unwrap();
+		assert_eq!(executions[0].value.code, "// Cargo.toml\n// src/main.rs");
+		assert_eq!(executions[0].value.flags, "crate");
+	}
+
+	fn only_button(components: &[Component]) -> &Button {
+		match components {
+			[Component::ActionRow(row)] => match row.components.as_slice() {
+				[Component::Button(button)] => button,
+				other => panic!("expected a button, got {:?}", other),
+			},
+			other => panic!("expected one row, got {:?}", other),
+		}
+	}
+
+	#[tokio::test]
+	async fn share_button_only_with_somewhere_to_share() {
+		let playground = Playground::new("fn main() {}", &RunOptions::default());
+		let shared: Arc<dyn ExecutionBackend> = backend(None, Some("https://play.rust-lang.org/?gist=1"));
+		let button = only_button(&share_button(&shared, &playground, Some(7)).await).clone();
+		assert_eq!(button.custom_id.as_deref(), Some("share:7"));
+		assert_eq!(button.url, None);
+
+		// Runs that weren't recorded can't be found again to share.
+		assert!(share_button(&shared, &playground, None).await.is_empty());
+		let unshared: Arc<dyn ExecutionBackend> = backend(None, None);
+		assert!(share_button(&unshared, &playground, Some(7)).await.is_empty());
+	}
+
+	#[tokio::test]
+	async fn upfront_sharing_links_straight_to_the_code() {
+		let playground = Playground::new("fn main() {}", &RunOptions::default());
+		let pastes: Arc<dyn ExecutionBackend> = Arc::new(FakeBackend {
+			share: Some(String::from("https://pastes.example.com/1")),
+			upfront: true,
+			..Default::default()
+		});
+		// Unrecorded runs and crates get one too, there's nothing to look up.
+		let button = only_button(&share_button(&pastes, &playground, None).await).clone();
+		assert_eq!(button.style, ButtonStyle::Link);
+		assert_eq!(button.url.as_deref(), Some("https://pastes.example.com/1"));
+	}
+}
@@ -0,0 +1,352 @@
+use std::{
+	collections::{BTreeSet, HashMap},
+	sync::{atomic::{AtomicU64, Ordering}, Mutex},
+	time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use twilight_http::Client;
+use twilight_model::{
+	application::{
+		component::{action_row::ActionRow, button::{Button, ButtonStyle}, Component},
+		interaction::MessageComponentInteraction,
+	},
+	channel::{message::MessageFlags, Message},
+	http::interaction::{InteractionResponse, InteractionResponseData, InteractionResponseType},
+	id::{marker::{ChannelMarker, GuildMarker, InteractionMarker, UserMarker}, Id},
+};
+
+use crate::audit;
+use crate::code::RunOptions;
+use crate::config::{self, ScreenAction};
+use crate::error::BotError;
+use crate::handler::{ComponentHandler, Context, Invoker};
+use crate::play::CrateFile;
+use crate::replies::ThreadReplies;
+use crate::run;
+
+/// How long code waiting for "Run anyway" to be pressed is kept around.
+const PENDING_LIFETIME: Duration = Duration::from_secs(15 * 60);
+
+/// Something that looks over code before it's run. Adding one is implementing
+/// this and putting it in `SCREENERS`.
+pub trait Screener: Send + Sync {
+	/// What `code` matched in `guild`.
+	fn screen(&self, guild: Id<GuildMarker>, code: &str) -> Vec<Finding>;
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+	/// What was matched, like "uses the network".
+	pub rule: String,
+	pub action: ScreenAction,
+}
+
+static SCREENERS: Lazy<Vec<Box<dyn Screener>>> = Lazy::new(|| vec![Box::new(PatternScreener)]);
+
+/// The guild's `screening` rules from the config.
+pub struct PatternScreener;
+
+/// Patterns that have been compiled, so they aren't for every run.
+static PATTERNS: Lazy<Mutex<HashMap<String, Regex>>> = Lazy::new(Default::default);
+
+impl Screener for PatternScreener {
+	fn screen(&self, guild: Id<GuildMarker>, code: &str) -> Vec<Finding> {
+		let config = config::get();
+		let rules = match config.guild(guild) {
+			Some(guild) => &guild.screening,
+			None => return Vec::new(),
+		};
+
+		let mut patterns = PATTERNS.lock().unwrap();
+		rules.iter()
+			.filter(|rule| {
+				if !patterns.contains_key(&rule.pattern) {
+					// The config's been checked, so this always works.
+					match Regex::new(&rule.pattern) {
+						Ok(regex) => patterns.insert(rule.pattern.clone(), regex),
+						Err(_) => return false,
+					};
+				}
+				patterns[&rule.pattern].is_match(code)
+			})
+			.map(|rule| Finding { rule: rule.name.clone(), action: rule.action })
+			.collect()
+	}
+}
+
+/// Everything every screener found in `code`.
+pub fn screen(guild: Id<GuildMarker>, code: &str) -> Vec<Finding> {
+	SCREENERS.iter().flat_map(|screener| screener.screen(guild, code)).collect()
+}
+
+/// The `.rs` files in a crate, all together to be screened.
+pub fn crate_code(files: &[CrateFile]) -> String {
+	files.iter()
+		.filter(|file| file.path.ends_with(".rs"))
+		.map(|file| String::from_utf8_lossy(&file.contents))
+		.collect::<Vec<_>>()
+		.join("\n")
+}
+
+/// Code waiting for whoever ran it to press "Run anyway".
+struct Pending {
+	user: Id<UserMarker>,
+	code: String,
+	options: RunOptions,
+	created: Instant,
+}
+
+static PENDING: Lazy<Mutex<HashMap<u64, Pending>>> = Lazy::new(Default::default);
+static NEXT_PENDING: AtomicU64 = AtomicU64::new(1);
+
+/// Keeps code until "Run anyway" is pressed, giving back the button to press.
+fn hold(user: Id<UserMarker>, code: &str, options: RunOptions) -> Vec<Component> {
+	let id = NEXT_PENDING.fetch_add(1, Ordering::Relaxed);
+	let mut pending = PENDING.lock().unwrap();
+	pending.retain(|_, pending| pending.created.elapsed() < PENDING_LIFETIME);
+	pending.insert(id, Pending { user, code: code.to_string(), options, created: Instant::now() });
+
+	vec![Component::ActionRow(ActionRow {
+		components: vec![Component::Button(Button {
+			custom_id: Some(format!("screen:{}", id)),
+			disabled: false,
+			emoji: None,
+			label: Some(String::from("Run anyway")),
+			style: ButtonStyle::Danger,
+			url: None,
+		})],
+	})]
+}
+
+/// What to do about some code, and what to tell whoever ran it. Confirming
+/// needs the options to run it with later, without them (for crates and
+/// compiles) it's refused.
+fn decide(findings: &[Finding], options: Option<RunOptions>) -> Option<(ScreenAction, String)> {
+	// Two rules (or screeners) can go by the same name, it's only said once.
+	let rules = findings.iter()
+		.map(|finding| finding.rule.as_str())
+		.collect::<BTreeSet<_>>()
+		.into_iter()
+		.collect::<Vec<_>>()
+		.join(", ");
+
+	let action = findings.iter().map(|finding| finding.action).max()?;
+	Some(match (action, options) {
+		(ScreenAction::Warn, _) => (action, format!("⚠️ Heads up, that code {}. It's been run, and the mods can see it.", rules)),
+		(ScreenAction::Confirm, Some(_)) => (action, format!("🤔 That code {}. Are you sure you want to run it? The mods can see it either way.", rules)),
+		(ScreenAction::Confirm, None) => (ScreenAction::Refuse, format!("🚫 Not running that, it {}. Only code blocks can be run anyway.", rules)),
+		(ScreenAction::Refuse, _) => (action, format!("🚫 Not running that, it {}.", rules)),
+	})
+}
+
+/// Screens code someone's running with an interaction, before it's been
+/// answered. If the code matched anything, telling them so is the answer (only
+/// they see it), otherwise the interaction's deferred. Gives back whether to go
+/// ahead and run it, which it isn't if it was refused or needs "Run anyway"
+/// pressed first.
+pub async fn check_interaction(
+	ctx: &Context,
+	id: Id<InteractionMarker>,
+	token: &str,
+	invoker: &Invoker,
+	channel: Id<ChannelMarker>,
+	code: &str,
+	options: Option<RunOptions>,
+) -> Result<bool, BotError> {
+	let findings = screen(invoker.guild, code);
+	let (action, content) = match decide(&findings, options) {
+		Some(decision) => decision,
+		None => {
+			ctx.defer(id, token).await?;
+			return Ok(true);
+		}
+	};
+
+	let components = match (action, options) {
+		(ScreenAction::Confirm, Some(options)) => hold(invoker.user.id, code, options),
+		_ => vec![],
+	};
+	ctx.interaction()
+		.create_response(id, token, &InteractionResponse {
+			kind: InteractionResponseType::ChannelMessageWithSource,
+			data: Some(InteractionResponseData {
+				content: Some(content),
+				components: Some(components),
+				flags: Some(MessageFlags::EPHEMERAL),
+				..Default::default()
+			}),
+		})
+		.exec()
+		.await?;
+	log(&ctx.http, invoker.guild, invoker.user.id, channel, code, &findings, action).await;
+
+	Ok(action == ScreenAction::Warn)
+}
+
+/// `check_interaction` for an interaction that's already been answered, with
+/// what it matched as a followup. Not after a defer, the first followup takes
+/// the deferred answer's place and everyone would see it.
+pub async fn check_followup(
+	ctx: &Context,
+	token: &str,
+	invoker: &Invoker,
+	channel: Id<ChannelMarker>,
+	code: &str,
+	options: Option<RunOptions>,
+) -> Result<bool, BotError> {
+	let findings = screen(invoker.guild, code);
+	let (action, content) = match decide(&findings, options) {
+		Some(decision) => decision,
+		None => return Ok(true),
+	};
+	log(&ctx.http, invoker.guild, invoker.user.id, channel, code, &findings, action).await;
+
+	let followup = ctx.interaction();
+	let followup = followup.create_followup(token)
+		.content(&content)?
+		.flags(MessageFlags::EPHEMERAL);
+	match (action, options) {
+		(ScreenAction::Confirm, Some(options)) => {
+			followup.components(&hold(invoker.user.id, code, options))?.exec().await?;
+			Ok(false)
+		}
+		_ => {
+			followup.exec().await?;
+			Ok(action == ScreenAction::Warn)
+		}
+	}
+}
+
+/// Screens code from a message in a run channel, saying what it matched in a
+/// notice on the message. Gives back whether to go ahead and run it.
+pub async fn check_message(
+	http: &Client,
+	replies: &mut ThreadReplies<'_>,
+	message: &Message,
+	guild: Id<GuildMarker>,
+	code: &str,
+	options: Option<RunOptions>,
+) -> Result<bool, BotError> {
+	let findings = screen(guild, code);
+	let (action, content) = match decide(&findings, options) {
+		Some(decision) => decision,
+		None => return Ok(true),
+	};
+	log(http, guild, message.author.id, message.channel_id, code, &findings, action).await;
+
+	match (action, options) {
+		(ScreenAction::Confirm, Some(options)) => {
+			replies.notice_with(http, &content, &hold(message.author.id, code, options)).await?;
+			Ok(false)
+		}
+		_ => {
+			replies.notice(http, &content).await?;
+			Ok(action == ScreenAction::Warn)
+		}
+	}
+}
+
+async fn log(
+	http: &Client,
+	guild: Id<GuildMarker>,
+	user: Id<UserMarker>,
+	channel: Id<ChannelMarker>,
+	code: &str,
+	findings: &[Finding],
+	action: ScreenAction,
+) {
+	audit::log(http, guild, audit::Entry::Screened {
+		user,
+		channel,
+		rules: findings.iter().map(|finding| finding.rule.clone()).collect(),
+		action,
+		code: code.to_string(),
+	}).await;
+}
+
+/// "Run anyway" on code that needed confirming, only for whoever ran it.
+pub struct ConfirmButtons;
+
+#[async_trait]
+impl ComponentHandler for ConfirmButtons {
+	fn handles(&self, custom_id: &str) -> bool {
+		custom_id.starts_with("screen:")
+	}
+
+	async fn run(&self, ctx: &Context, msgcmp: &MessageComponentInteraction, invoker: &Invoker) -> Result<(), BotError> {
+		let id = msgcmp.data.custom_id.trim_start_matches("screen:").parse::<u64>()
+			.map_err(|_| BotError::Validation(String::from("That button's broken, sorry.")))?;
+
+		let pending = {
+			let mut pending = PENDING.lock().unwrap();
+			match pending.get(&id) {
+				Some(held) if held.user != invoker.user.id => {
+					return Err(BotError::Permission(String::from("Only whoever ran that can run it anyway.")));
+				}
+				Some(held) if held.created.elapsed() < PENDING_LIFETIME => {
+					// Paid for before it's taken, so being rate limited leaves it there to press again.
+					run::pay(invoker, msgcmp.channel_id, 1)?;
+					pending.remove(&id)
+				}
+				_ => None,
+			}
+		};
+		let pending = pending.ok_or_else(|| BotError::Validation(String::from("That's been waiting too long, run it again.")))?;
+
+		ctx.defer(msgcmp.id, &msgcmp.token).await?;
+		let filename = format!("{}-{}.txt", invoker.guild.get(), invoker.user.id.get());
+		run::for_interaction(ctx, &msgcmp.token, invoker, msgcmp.channel_id, &pending.code, &pending.options, &filename).await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn finding(rule: &str, action: ScreenAction) -> Finding {
+		Finding { rule: rule.to_string(), action }
+	}
+
+	#[test]
+	fn nothing_found_nothing_done() {
+		assert_eq!(decide(&[], Some(RunOptions::default())), None);
+	}
+
+	#[test]
+	fn most_drastic_action_wins() {
+		let findings = [
+			finding("prints in a loop", ScreenAction::Warn),
+			finding("uses the network", ScreenAction::Confirm),
+		];
+		let (action, content) = decide(&findings, Some(RunOptions::default())).unwrap();
+		assert_eq!(action, ScreenAction::Confirm);
+		assert!(content.contains("prints in a loop, uses the network"));
+
+		let findings = [finding("spawns threads in a loop", ScreenAction::Refuse), finding("uses the network", ScreenAction::Confirm)];
+		assert_eq!(decide(&findings, Some(RunOptions::default())).unwrap().0, ScreenAction::Refuse);
+	}
+
+	#[test]
+	fn confirming_without_options_is_refusing() {
+		let findings = [finding("uses the network", ScreenAction::Confirm)];
+		let (action, content) = decide(&findings, None).unwrap();
+		assert_eq!(action, ScreenAction::Refuse);
+		assert!(content.starts_with("🚫"));
+		assert_eq!(decide(&[finding("spins", ScreenAction::Warn)], None).unwrap().0, ScreenAction::Warn);
+	}
+
+	#[test]
+	fn rules_are_only_named_once() {
+		let findings = [
+			finding("uses the network", ScreenAction::Confirm),
+			finding("runs other programs", ScreenAction::Confirm),
+			finding("uses the network", ScreenAction::Warn),
+		];
+		let (_, content) = decide(&findings, Some(RunOptions::default())).unwrap();
+		assert_eq!(content.matches("uses the network").count(), 1);
+		assert!(content.contains("runs other programs, uses the network"));
+	}
+}
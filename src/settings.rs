@@ -0,0 +1,74 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use once_cell::sync::Lazy;
+use tracing::error;
+use twilight_model::id::{marker::{ChannelMarker, GuildMarker}, Id};
+
+use crate::config::{self, GuildConfig};
+use crate::storage::{self, Override, Setting};
+
+/// Whether each channel or role was added (`true`) or taken out, by guild and setting.
+type Overrides = HashMap<(Id<GuildMarker>, Setting), HashMap<u64, bool>>;
+
+/// What admins have added or taken out with `/admin`, loaded from storage the first time it's needed.
+static OVERRIDES: Lazy<RwLock<Overrides>> = Lazy::new(|| {
+	let mut overrides = Overrides::new();
+	match storage::get().overrides() {
+		Ok(changes) => for change in changes {
+			overrides.entry((change.guild, change.setting)).or_default().insert(change.value, change.enabled);
+		},
+		Err(why) => error!("Couldn't load the changes made with /admin: {}", why),
+	}
+	RwLock::new(overrides)
+});
+
+fn from_config(guild: &GuildConfig, setting: Setting) -> &[u64] {
+	match setting {
+		Setting::RunChannel => &guild.channels,
+		Setting::BannedRole => &guild.banned_roles,
+	}
+}
+
+/// A guild's run channels or banned roles: what's in the config, with whatever
+/// admins have added or taken out since.
+pub fn get(guild: Id<GuildMarker>, setting: Setting) -> Vec<u64> {
+	let config = config::get();
+	let mut values = match config.guild(guild) {
+		Some(config) => from_config(config, setting).to_vec(),
+		None => return Vec::new(),
+	};
+
+	if let Some(changes) = OVERRIDES.read().unwrap().get(&(guild, setting)) {
+		values.retain(|value| changes.get(value) != Some(&false));
+		for (&value, &enabled) in changes {
+			if enabled && !values.contains(&value) {
+				values.push(value);
+			}
+		}
+	}
+	values
+}
+
+pub fn contains(guild: Id<GuildMarker>, setting: Setting, value: u64) -> bool {
+	let changed = OVERRIDES.read().unwrap()
+		.get(&(guild, setting))
+		.and_then(|changes| changes.get(&value).copied());
+
+	match changed {
+		Some(enabled) => enabled,
+		None => config::get().guild(guild).is_some_and(|config| from_config(config, setting).contains(&value)),
+	}
+}
+
+/// Adds something to one of a guild's settings, or takes it out. It's saved,
+/// and takes effect straight away.
+pub fn set(guild: Id<GuildMarker>, setting: Setting, value: u64, enabled: bool) -> rusqlite::Result<()> {
+	storage::get().set_override(&Override { guild, setting, value, enabled })?;
+	OVERRIDES.write().unwrap().entry((guild, setting)).or_default().insert(value, enabled);
+	Ok(())
+}
+
+/// Whether code in messages in `channel` gets run.
+pub fn is_run_channel(guild: Option<Id<GuildMarker>>, channel: Id<ChannelMarker>) -> bool {
+	guild.is_some_and(|guild| contains(guild, Setting::RunChannel, channel.get()))
+}
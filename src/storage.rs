@@ -0,0 +1,587 @@
+use std::{
+	hash::Hasher,
+	path::Path,
+	sync::Mutex,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use fnv::FnvHasher;
+use once_cell::sync::OnceCell;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use twilight_model::id::{marker::{ChannelMarker, GuildMarker, MessageMarker, UserMarker}, Id};
+
+
+static STORAGE: OnceCell<Storage> = OnceCell::new();
+
+/// Opens the database in `dir`, once at startup so a `data_dir` that can't be
+/// written to stops the bot before it connects.
+pub fn init(dir: &Path) -> rusqlite::Result<()> {
+	let storage = Storage::open(dir)?;
+	let _ = STORAGE.set(storage);
+	Ok(())
+}
+
+/// The bot's database, in `data_dir`.
+pub fn get() -> &'static Storage {
+	STORAGE.get().expect("the database is opened at startup")
+}
+
+const SCHEMA: &str = "
+	CREATE TABLE IF NOT EXISTS executions (
+		id INTEGER PRIMARY KEY,
+		user_id INTEGER NOT NULL,
+		guild_id INTEGER,
+		channel_id INTEGER NOT NULL,
+		code_hash TEXT NOT NULL,
+		code TEXT NOT NULL,
+		flags TEXT NOT NULL,
+		success INTEGER NOT NULL,
+		duration_ms INTEGER NOT NULL,
+		created_at INTEGER NOT NULL
+	);
+	CREATE INDEX IF NOT EXISTS executions_by_user_in_guild ON executions (user_id, guild_id, id);
+
+	CREATE TABLE IF NOT EXISTS projects (
+		id INTEGER PRIMARY KEY,
+		user_id INTEGER NOT NULL,
+		guild_id INTEGER NOT NULL,
+		name TEXT NOT NULL,
+		description TEXT,
+		github TEXT,
+		crates_io INTEGER NOT NULL,
+		message_id INTEGER,
+		created_at INTEGER NOT NULL
+	);
+
+	CREATE TABLE IF NOT EXISTS replies (
+		message_id INTEGER PRIMARY KEY,
+		thread_id INTEGER,
+		results TEXT NOT NULL,
+		notices TEXT NOT NULL,
+		runs INTEGER NOT NULL,
+		source INTEGER NOT NULL
+	);
+
+	CREATE TABLE IF NOT EXISTS guild_settings (
+		guild_id INTEGER NOT NULL,
+		setting TEXT NOT NULL,
+		value INTEGER NOT NULL,
+		enabled INTEGER NOT NULL,
+		PRIMARY KEY (guild_id, setting, value)
+	);
+
+	CREATE TABLE IF NOT EXISTS user_bans (
+		guild_id INTEGER NOT NULL,
+		user_id INTEGER NOT NULL,
+		moderator_id INTEGER NOT NULL,
+		reason TEXT,
+		until INTEGER,
+		created_at INTEGER NOT NULL,
+		PRIMARY KEY (guild_id, user_id)
+	);
+";
+
+/// Something that was run, by anyone, anywhere.
+#[derive(Debug, Clone)]
+pub struct Execution {
+	pub user: Id<UserMarker>,
+	pub guild: Option<Id<GuildMarker>>,
+	pub channel: Id<ChannelMarker>,
+	pub code_hash: String,
+	pub code: String,
+	/// The options it was run with, as `RunOptions::to_flags` gives them.
+	pub flags: String,
+	pub success: bool,
+	pub duration_ms: u64,
+}
+
+/// Something read back out of the database.
+#[derive(Debug, Clone)]
+pub struct Stored<T> {
+	pub id: i64,
+	/// Unix seconds.
+	pub created_at: i64,
+	pub value: T,
+}
+
+/// A `/project` that was shown off.
+#[derive(Debug, Clone)]
+pub struct Project {
+	pub user: Id<UserMarker>,
+	pub guild: Id<GuildMarker>,
+	pub name: String,
+	pub description: Option<String>,
+	pub github: Option<String>,
+	pub crates_io: bool,
+	/// The message it was posted as, if posting it worked.
+	pub message: Option<Id<MessageMarker>>,
+}
+
+/// What the bot posted for a message in a run channel, so an edit to the
+/// message can update the results instead of posting new ones, and deleting it
+/// can clean them up.
+#[derive(Debug, Clone)]
+pub struct Replies {
+	/// The thread on the message, if any results were posted.
+	pub thread: Option<Id<ChannelMarker>>,
+	/// The results in the thread, in the order they were posted.
+	pub results: Vec<Id<MessageMarker>>,
+	/// Replies in the channel itself, like a block's options being wrong.
+	pub notices: Vec<Id<MessageMarker>>,
+	/// How many times the message has been run, edits included.
+	pub runs: u32,
+	/// A hash of what was run, so edits that don't change it (like Discord
+	/// adding an embed) don't run it again.
+	pub source: u64,
+}
+
+/// The lists in a guild's config that admins can change with `/admin`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Setting {
+	RunChannel,
+	BannedRole,
+}
+
+impl Setting {
+	fn as_str(self) -> &'static str {
+		match self {
+			Setting::RunChannel => "run_channel",
+			Setting::BannedRole => "banned_role",
+		}
+	}
+
+	fn parse(setting: &str) -> Option<Self> {
+		match setting {
+			"run_channel" => Some(Setting::RunChannel),
+			"banned_role" => Some(Setting::BannedRole),
+			_ => None,
+		}
+	}
+}
+
+/// Something an admin added to (or took out of) one of a guild's `Setting`s,
+/// on top of what's in the config.
+#[derive(Debug, Clone)]
+pub struct Override {
+	pub guild: Id<GuildMarker>,
+	pub setting: Setting,
+	/// The channel or role id.
+	pub value: u64,
+	/// Whether it was added rather than taken out.
+	pub enabled: bool,
+}
+
+/// Someone who isn't allowed to use the bot in a guild.
+#[derive(Debug, Clone)]
+pub struct UserBan {
+	pub user: Id<UserMarker>,
+	pub guild: Id<GuildMarker>,
+	/// Whoever banned them.
+	pub moderator: Id<UserMarker>,
+	pub reason: Option<String>,
+	/// Unix seconds, they're banned for good without it.
+	pub until: Option<i64>,
+}
+
+/// A SQLite database holding everything the bot remembers.
+pub struct Storage {
+	connection: Mutex<Connection>,
+}
+
+impl Storage {
+	/// Opens (or makes) the database in `dir`.
+	pub fn open(dir: &Path) -> rusqlite::Result<Self> {
+		std::fs::create_dir_all(dir).map_err(|e| rusqlite::Error::ToSqlConversionFailure(e.into()))?;
+
+		let connection = Connection::open(dir.join("run-my-rust.db"))?;
+		connection.execute_batch(SCHEMA)?;
+
+		Ok(Storage { connection: Mutex::new(connection) })
+	}
+
+	/// Saves an execution, giving back its id.
+	pub fn record_execution(&self, execution: &Execution) -> rusqlite::Result<i64> {
+		let connection = self.connection.lock().unwrap();
+		connection.execute(
+			"INSERT INTO executions (user_id, guild_id, channel_id, code_hash, code, flags, success, duration_ms, created_at)
+			VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+			params![
+				execution.user.get() as i64,
+				execution.guild.map(|g| g.get() as i64),
+				execution.channel.get() as i64,
+				execution.code_hash,
+				execution.code,
+				execution.flags,
+				execution.success,
+				execution.duration_ms as i64,
+				now(),
+			],
+		)?;
+		Ok(connection.last_insert_rowid())
+	}
+
+	pub fn execution(&self, id: i64) -> rusqlite::Result<Option<Stored<Execution>>> {
+		self.connection.lock().unwrap()
+			.query_row("SELECT * FROM executions WHERE id = ?1", [id], execution)
+			.optional()
+	}
+
+	/// A user's executions in a guild, newest first.
+	pub fn executions_by(
+		&self,
+		user: Id<UserMarker>,
+		guild: Id<GuildMarker>,
+		limit: u32,
+		offset: u32,
+	) -> rusqlite::Result<Vec<Stored<Execution>>> {
+		let connection = self.connection.lock().unwrap();
+		let mut statement = connection.prepare(
+			"SELECT * FROM executions WHERE user_id = ?1 AND guild_id = ?2 ORDER BY id DESC LIMIT ?3 OFFSET ?4"
+		)?;
+		let executions = statement.query_map(params![user.get() as i64, guild.get() as i64, limit, offset], execution)?;
+		executions.collect()
+	}
+
+	pub fn count_executions_by(&self, user: Id<UserMarker>, guild: Id<GuildMarker>) -> rusqlite::Result<u32> {
+		self.connection.lock().unwrap()
+			.query_row(
+				"SELECT COUNT(*) FROM executions WHERE user_id = ?1 AND guild_id = ?2",
+				[user.get() as i64, guild.get() as i64],
+				|row| row.get(0),
+			)
+	}
+
+	/// Saves a project, giving back its id.
+	pub fn record_project(&self, project: &Project) -> rusqlite::Result<i64> {
+		let connection = self.connection.lock().unwrap();
+		connection.execute(
+			"INSERT INTO projects (user_id, guild_id, name, description, github, crates_io, message_id, created_at)
+			VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+			params![
+				project.user.get() as i64,
+				project.guild.get() as i64,
+				project.name,
+				project.description,
+				project.github,
+				project.crates_io,
+				project.message.map(|m| m.get() as i64),
+				now(),
+			],
+		)?;
+		Ok(connection.last_insert_rowid())
+	}
+
+	pub fn replies(&self, message: Id<MessageMarker>) -> rusqlite::Result<Option<Replies>> {
+		self.connection.lock().unwrap()
+			.query_row("SELECT * FROM replies WHERE message_id = ?1", [message.get() as i64], |row| Ok(Replies {
+				thread: optional_id(row, "thread_id")?,
+				results: ids(row, "results")?,
+				notices: ids(row, "notices")?,
+				runs: row.get("runs")?,
+				source: row.get::<_, i64>("source")? as u64,
+			}))
+			.optional()
+	}
+
+	pub fn set_replies(&self, message: Id<MessageMarker>, replies: &Replies) -> rusqlite::Result<()> {
+		let join = |ids: &[Id<MessageMarker>]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",");
+
+		self.connection.lock().unwrap().execute(
+			"INSERT OR REPLACE INTO replies (message_id, thread_id, results, notices, runs, source)
+			VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+			params![
+				message.get() as i64,
+				replies.thread.map(|t| t.get() as i64),
+				join(&replies.results),
+				join(&replies.notices),
+				replies.runs,
+				replies.source as i64,
+			],
+		)?;
+		Ok(())
+	}
+
+	pub fn set_override(&self, change: &Override) -> rusqlite::Result<()> {
+		self.connection.lock().unwrap().execute(
+			"INSERT OR REPLACE INTO guild_settings (guild_id, setting, value, enabled) VALUES (?1, ?2, ?3, ?4)",
+			params![change.guild.get() as i64, change.setting.as_str(), change.value as i64, change.enabled],
+		)?;
+		Ok(())
+	}
+
+	pub fn overrides(&self) -> rusqlite::Result<Vec<Override>> {
+		let connection = self.connection.lock().unwrap();
+		let mut statement = connection.prepare("SELECT * FROM guild_settings")?;
+		let overrides = statement.query_map([], |row| Ok((
+			id(row, "guild_id")?,
+			row.get::<_, String>("setting")?,
+			row.get::<_, i64>("value")? as u64,
+			row.get("enabled")?,
+		)))?;
+
+		// Settings this version doesn't know about are left alone.
+		Ok(overrides.collect::<rusqlite::Result<Vec<_>>>()?
+			.into_iter()
+			.filter_map(|(guild, setting, value, enabled)| Some(Override {
+				guild,
+				setting: Setting::parse(&setting)?,
+				value,
+				enabled,
+			}))
+			.collect())
+	}
+
+	/// Bans someone, replacing any ban they already had.
+	pub fn ban_user(&self, ban: &UserBan) -> rusqlite::Result<()> {
+		self.connection.lock().unwrap().execute(
+			"INSERT OR REPLACE INTO user_bans (guild_id, user_id, moderator_id, reason, until, created_at)
+			VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+			params![
+				ban.guild.get() as i64,
+				ban.user.get() as i64,
+				ban.moderator.get() as i64,
+				ban.reason,
+				ban.until,
+				now(),
+			],
+		)?;
+		Ok(())
+	}
+
+	/// Lifts someone's ban, giving back whether they had one.
+	pub fn unban_user(&self, guild: Id<GuildMarker>, user: Id<UserMarker>) -> rusqlite::Result<bool> {
+		let removed = self.connection.lock().unwrap().execute(
+			"DELETE FROM user_bans WHERE guild_id = ?1 AND user_id = ?2",
+			params![guild.get() as i64, user.get() as i64],
+		)?;
+		Ok(removed > 0)
+	}
+
+	/// Someone's ban in a guild, if they have one that hasn't run out.
+	pub fn user_ban(&self, guild: Id<GuildMarker>, user: Id<UserMarker>) -> rusqlite::Result<Option<Stored<UserBan>>> {
+		self.connection.lock().unwrap()
+			.query_row(
+				"SELECT rowid AS id, * FROM user_bans
+				WHERE guild_id = ?1 AND user_id = ?2 AND (until IS NULL OR until > ?3)",
+				params![guild.get() as i64, user.get() as i64, now()],
+				|row| Ok(Stored {
+					id: row.get("id")?,
+					created_at: row.get("created_at")?,
+					value: UserBan {
+						user: id(row, "user_id")?,
+						guild: id(row, "guild_id")?,
+						moderator: id(row, "moderator_id")?,
+						reason: row.get("reason")?,
+						until: row.get("until")?,
+					},
+				}),
+			)
+			.optional()
+	}
+
+	/// Forgets the replies to a message, giving back what they were.
+	pub fn remove_replies(&self, message: Id<MessageMarker>) -> rusqlite::Result<Option<Replies>> {
+		let replies = self.replies(message)?;
+		self.connection.lock().unwrap()
+			.execute("DELETE FROM replies WHERE message_id = ?1", [message.get() as i64])?;
+		Ok(replies)
+	}
+}
+
+/// Hashes text for keeping in the database or in file names. Unlike std's
+/// `DefaultHasher` it hashes the same on every version of Rust.
+pub fn stable_hash(text: &str) -> u64 {
+	let mut hasher = FnvHasher::default();
+	hasher.write(text.as_bytes());
+	hasher.finish()
+}
+
+/// The same code always hashes the same, so runs of it can be found again.
+pub fn code_hash(code: &str) -> String {
+	format!("{:016x}", stable_hash(code))
+}
+
+fn now() -> i64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default()
+}
+
+fn execution(row: &Row) -> rusqlite::Result<Stored<Execution>> {
+	Ok(Stored {
+		id: row.get("id")?,
+		created_at: row.get("created_at")?,
+		value: Execution {
+			user: id(row, "user_id")?,
+			guild: optional_id(row, "guild_id")?,
+			channel: id(row, "channel_id")?,
+			code_hash: row.get("code_hash")?,
+			code: row.get("code")?,
+			flags: row.get("flags")?,
+			success: row.get("success")?,
+			duration_ms: row.get::<_, i64>("duration_ms")? as u64,
+		},
+	})
+}
+
+/// Discord's ids fit in an `i64`, which is what SQLite stores, but can't be zero.
+fn id<T>(row: &Row, column: &str) -> rusqlite::Result<Id<T>> {
+	optional_id(row, column)?.ok_or_else(|| rusqlite::Error::InvalidColumnName(column.to_string()))
+}
+
+fn optional_id<T>(row: &Row, column: &str) -> rusqlite::Result<Option<Id<T>>> {
+	Ok(row.get::<_, Option<i64>>(column)?.and_then(|id| Id::new_checked(id as u64)))
+}
+
+/// Lists of message ids are kept comma separated in one column.
+fn ids(row: &Row, column: &str) -> rusqlite::Result<Vec<Id<MessageMarker>>> {
+	Ok(row.get::<_, String>(column)?
+		.split(',')
+		.filter_map(|id| id.parse().ok())
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+	use std::sync::atomic::{AtomicU32, Ordering};
+
+	use super::*;
+
+	/// A database in its own directory under the system temp dir, deleted afterwards.
+	struct TempStorage {
+		dir: PathBuf,
+		storage: Storage,
+	}
+
+	impl TempStorage {
+		fn new() -> Self {
+			static COUNTER: AtomicU32 = AtomicU32::new(0);
+			let dir = std::env::temp_dir().join(format!(
+				"run-my-rust-test-{}-{}",
+				std::process::id(),
+				COUNTER.fetch_add(1, Ordering::Relaxed)
+			));
+			let storage = Storage::open(&dir).unwrap();
+			TempStorage { dir, storage }
+		}
+	}
+
+	impl Drop for TempStorage {
+		fn drop(&mut self) {
+			let _ = std::fs::remove_dir_all(&self.dir);
+		}
+	}
+
+	const GUILD: u64 = 10;
+
+	fn execution(user: u64, code: &str) -> Execution {
+		Execution {
+			user: Id::new(user),
+			guild: Some(Id::new(GUILD)),
+			channel: Id::new(20),
+			code_hash: code_hash(code),
+			code: code.to_string(),
+			flags: String::from("stable,debug,2021"),
+			success: true,
+			duration_ms: 42,
+		}
+	}
+
+	#[test]
+	fn executions_round_trip() {
+		let temp = TempStorage::new();
+		let id = temp.storage.record_execution(&execution(1, "fn main() {}")).unwrap();
+
+		let stored = temp.storage.execution(id).unwrap().unwrap();
+		assert_eq!(stored.id, id);
+		assert_eq!(stored.value.user, Id::new(1));
+		assert_eq!(stored.value.guild, Some(Id::new(10)));
+		assert_eq!(stored.value.channel, Id::new(20));
+		assert_eq!(stored.value.code, "fn main() {}");
+		assert_eq!(stored.value.code_hash, code_hash("fn main() {}"));
+		assert_eq!(stored.value.flags, "stable,debug,2021");
+		assert!(stored.value.success);
+		assert_eq!(stored.value.duration_ms, 42);
+
+		assert!(temp.storage.execution(id + 1).unwrap().is_none());
+	}
+
+	#[test]
+	fn executions_by_pages_newest_first() {
+		let temp = TempStorage::new();
+		for i in 0..5 {
+			temp.storage.record_execution(&execution(1, &format!("run {}", i))).unwrap();
+		}
+		temp.storage.record_execution(&execution(2, "someone else")).unwrap();
+		temp.storage.record_execution(&Execution { guild: Some(Id::new(11)), ..execution(1, "somewhere else") }).unwrap();
+
+		assert_eq!(temp.storage.count_executions_by(Id::new(1), Id::new(GUILD)).unwrap(), 5);
+		assert_eq!(temp.storage.count_executions_by(Id::new(1), Id::new(11)).unwrap(), 1);
+		let codes = |limit, offset| temp.storage.executions_by(Id::new(1), Id::new(GUILD), limit, offset).unwrap()
+			.into_iter()
+			.map(|e| e.value.code)
+			.collect::<Vec<_>>();
+		assert_eq!(codes(2, 0), ["run 4", "run 3"]);
+		assert_eq!(codes(2, 2), ["run 2", "run 1"]);
+		assert_eq!(codes(2, 4), ["run 0"]);
+		assert!(codes(2, 6).is_empty());
+	}
+
+	#[test]
+	fn projects_are_recorded() {
+		let temp = TempStorage::new();
+		let id = temp.storage.record_project(&Project {
+			user: Id::new(1),
+			guild: Id::new(10),
+			name: String::from("ferris"),
+			description: None,
+			github: Some(String::from("https://github.com/ferris/ferris")),
+			crates_io: true,
+			message: Some(Id::new(30)),
+		}).unwrap();
+
+		let connection = temp.storage.connection.lock().unwrap();
+		let (name, github, crates_io, message): (String, Option<String>, bool, Option<i64>) = connection.query_row(
+			"SELECT name, github, crates_io, message_id FROM projects WHERE id = ?1",
+			[id],
+			|row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+		).unwrap();
+		assert_eq!(name, "ferris");
+		assert_eq!(github.as_deref(), Some("https://github.com/ferris/ferris"));
+		assert!(crates_io);
+		assert_eq!(message, Some(30));
+	}
+
+	#[test]
+	fn replies_round_trip_and_remove() {
+		let temp = TempStorage::new();
+		let message = Id::new(100);
+		assert!(temp.storage.replies(message).unwrap().is_none());
+
+		temp.storage.set_replies(message, &Replies {
+			thread: Some(Id::new(200)),
+			results: vec![Id::new(300), Id::new(301)],
+			notices: vec![],
+			runs: 2,
+			source: u64::MAX,
+		}).unwrap();
+
+		let replies = temp.storage.replies(message).unwrap().unwrap();
+		assert_eq!(replies.thread, Some(Id::new(200)));
+		assert_eq!(replies.results, [Id::new(300), Id::new(301)]);
+		assert!(replies.notices.is_empty());
+		assert_eq!(replies.runs, 2);
+		assert_eq!(replies.source, u64::MAX);
+
+		let removed = temp.storage.remove_replies(message).unwrap().unwrap();
+		assert_eq!(removed.results, replies.results);
+		assert!(temp.storage.replies(message).unwrap().is_none());
+		assert!(temp.storage.remove_replies(message).unwrap().is_none());
+	}
+
+	#[test]
+	fn hashes_are_stable() {
+		// FNV-1a of "hello", so a change to the hash is noticed before it's in the database.
+		assert_eq!(stable_hash("hello"), 0xa430d84680aabd0b);
+		assert_eq!(code_hash("hello"), "a430d84680aabd0b");
+	}
+}